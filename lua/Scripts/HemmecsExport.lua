@@ -1,5 +1,40 @@
+-- Must match PROTOCOL_VERSION in src/installer/constants.rs
+local PROTOCOL_VERSION = 3
+-- Replaced by the installer with the values from dcs-hemmecs.toml
+local HOST = "127.0.0.1"
+local PORT = 28561
+local SECRET = ""
+-- Either "tcp" or "udp"
+local TRANSPORT = "tcp"
+-- Either "json" or "binary"
+local ENCODING = "json"
+-- Most frames sent per second, or 0 to send one every DCS frame
+local EXPORT_RATE = 0
+-- How long a new client has to send the secret, in seconds of real time, which keeps passing
+-- while the game is paused
+local AUTH_TIMEOUT = 1
+-- How long a UDP subscriber can go without renewing its subscription, in seconds
+local SUBSCRIPTION_TIMEOUT = 5
+-- How often every section of a binary frame is sent, even if it didn't change, in frames
+local KEYFRAME_INTERVAL = 60
+
 local client = nil
 local server = nil
+-- Client which has connected but not sent the secret yet, and what it has sent so far
+local pending = nil
+local pending_input = ""
+local pending_deadline = 0
+local udp = nil
+-- Address of the application receiving the datagrams, when using UDP
+local subscriber = nil
+-- Number of the last datagram sent, so the application can notice lost and reordered ones
+local sequence = 0
+-- Sections of the last binary frame, which are left out of the next frames while they don't change
+local last_cp_params = nil
+local last_details = nil
+local frames_since_keyframe = 0
+-- When the next frame is due, in seconds of real time, so frames keep coming while the game is paused
+local next_export = 0
 
 package.path  = package.path..";"..lfs.currentdir().."/LuaSocket/?.lua"
 package.cpath = package.cpath..";"..lfs.currentdir().."/LuaSocket/?.dll"
@@ -15,6 +50,24 @@ local function log_error(str)
     log.write("HEMMECS.EXPORT", log.ERROR, str)
 end
 
+-- Adler-32 checksum of this script, so the application can tell if it is outdated
+local function scriptHash()
+    local file = io.open(lfs.writedir().."Scripts/HemmecsExport.lua", "rb")
+    if file == nil then
+        return 0
+    end
+    local contents = file:read("*a")
+    file:close()
+    local a, b = 1, 0
+    for i = 1, #contents do
+        a = (a + contents:byte(i)) % 65521
+        b = (b + a) % 65521
+    end
+    return b * 65536 + a
+end
+
+local script_hash = scriptHash()
+
 local function same_weapon(a, b)
     return a.level1 == b.level1
         and a.level2 == b.level2
@@ -22,12 +75,96 @@ local function same_weapon(a, b)
         and a.level4 == b.level4
 end
 
-local function exportData()
+-- Listing every object in the mission is slow on busy servers, so their coalitions are only looked
+-- up again every few seconds, or every second while a contact is not known yet
+local COALITIONS_INTERVAL = 5
+local UNKNOWN_CONTACT_INTERVAL = 1
+local coalitions = {}
+local coalitions_time = nil
+
+local function refreshCoalitions(time)
+    coalitions = {}
+    coalitions_time = time
+    -- Servers may disable exporting world objects, in which case targets stay unidentified
+    local has_world_objects, world_objects = pcall(LoGetWorldObjects)
+    if has_world_objects and world_objects ~= nil then
+        for id, object in pairs(world_objects) do
+            coalitions[id] = { coalition = object.CoalitionID, country = object.Country }
+        end
+    end
+end
+
+local function hasUnknown(contacts)
+    if contacts ~= nil then
+        for _, contact in pairs(contacts) do
+            if coalitions[contact.ID] == nil then
+                return true
+            end
+        end
+    end
+    return false
+end
+
+local function updateCoalitions(targets, datalink)
+    local time = LoGetModelTime()
+    -- The model time goes back when the mission is restarted
+    if coalitions_time == nil or time < coalitions_time then
+        refreshCoalitions(time)
+        return
+    end
+    local age = time - coalitions_time
+    if age >= COALITIONS_INTERVAL
+        or (age >= UNKNOWN_CONTACT_INTERVAL and (hasUnknown(targets) or hasUnknown(datalink))) then
+        refreshCoalitions(time)
+    end
+end
+
+-- Tags each contact with the coalition and country of the matching world object, if known
+local function identify(contacts)
+    if contacts == nil then
+        return
+    end
+    for _, contact in pairs(contacts) do
+        local known = coalitions[contact.ID]
+        if known ~= nil then
+            contact.coalition = known.coalition
+            contact.country = known.country
+        end
+    end
+end
+
+local function encodeHello()
+    return json:encode({
+        hello = {
+            protocol = PROTOCOL_VERSION,
+            script_hash = script_hash,
+        }
+    })
+end
+
+-- The F10 map shows up as a camera high above the aircraft, looking straight down
+local MAP_CAMERA_HEIGHT = 1000
+
+local function viewType(cam, alt)
+    if cam.x.y < -0.99 and cam.p.y - (alt or 0) > MAP_CAMERA_HEIGHT then
+        return "map"
+    end
+    return "normal"
+end
+
+local function collectData(seq)
     local cp_params = list_cockpit_params()
     local pitch, bank, yaw = LoGetADIPitchBankYaw()
     local weapons = nil
     local payload = LoGetPayloadInfo()
     local player = LoGetSelfData()
+    local targets = LoGetTargetInformation()
+    local datalink = LoGetWingTargets()
+    local cam = LoGetCameraPosition()
+    local alt = LoGetAltitudeAboveSeaLevel()
+    updateCoalitions(targets, datalink)
+    identify(targets)
+    identify(datalink)
     if payload ~= nil then
         local selected = payload.Stations[payload.CurrentStation]
         if selected ~= nil then
@@ -51,27 +188,181 @@ local function exportData()
             }
         end
     end
-    local data = json:encode({
+    return {
         cp_params = cp_params,
         time = LoGetModelTime(),
         ias = LoGetIndicatedAirSpeed(),
         mach = LoGetMachNumber(),
-        alt = LoGetAltitudeAboveSeaLevel(),
+        alt = alt,
         rad_alt = LoGetAltitudeAboveGroundLevel(),
         pitch = pitch,
         bank = bank,
         yaw = yaw,
         aoa = LoGetAngleOfAttack(),
         g = LoGetAccelerationUnits(),
-        cam = LoGetCameraPosition(),
+        cam = cam,
         engine_data = LoGetEngineInfo(),
-        targets = LoGetTargetInformation(),
+        targets = targets,
         wingmen = LoGetWingInfo(),
-        datalink = LoGetWingTargets(),
-        unit = player.Name,
-        weapons = weapons
-    })
-    return client:send(data.."\n")
+        datalink = datalink,
+        unit = player and player.Name,
+        coalition = player and player.CoalitionID,
+        position = player and player.Position,
+        velocity = player and LoGetVectorVelocity(),
+        view = viewType(cam, alt),
+        -- There is no aircraft to get data from once it has been destroyed
+        dead = player == nil,
+        weapons = weapons,
+        seq = seq
+    }
+end
+
+local function encodeU32(n)
+    return string.char(n % 256, math.floor(n / 256) % 256, math.floor(n / 65536) % 256, math.floor(n / 16777216) % 256)
+end
+
+-- Little-endian IEEE 754 single precision float, since this version of Lua has no string.pack
+local function encodeFloat(x)
+    x = x or 0
+    if x ~= x then
+        return string.char(0, 0, 192, 127)
+    end
+    local sign = 0
+    if x < 0 or (x == 0 and 1 / x < 0) then
+        sign = 1
+        x = -x
+    end
+    local mantissa, exponent = 0, 0
+    if x == math.huge then
+        exponent = 255
+    elseif x > 0 then
+        -- x = m * 2^e, where 0.5 <= m < 1
+        local m, e = math.frexp(x)
+        exponent = e + 126
+        if exponent <= 0 then
+            -- Subnormal number
+            mantissa = math.floor(m * 2 ^ (23 + exponent) + 0.5)
+            exponent = 0
+        else
+            mantissa = math.floor((m * 2 - 1) * 2 ^ 23 + 0.5)
+        end
+        if mantissa >= 2 ^ 23 then
+            -- Rounded up to the next power of two
+            mantissa = 0
+            exponent = exponent + 1
+        end
+        if exponent >= 255 then
+            mantissa = 0
+            exponent = 255
+        end
+    end
+    return string.char(
+        mantissa % 256,
+        math.floor(mantissa / 256) % 256,
+        math.floor(mantissa / 65536) + (exponent % 2) * 128,
+        sign * 128 + math.floor(exponent / 2)
+    )
+end
+
+local function encodeSection(section)
+    return encodeU32(#section)..section
+end
+
+-- Compares nested tables field by field, which is much cheaper than encoding them every frame
+local function sameValue(a, b)
+    if a == b or (a ~= a and b ~= b) then
+        return true
+    end
+    if type(a) ~= "table" or type(b) ~= "table" then
+        return false
+    end
+    for key, value in pairs(a) do
+        if not sameValue(value, b[key]) then
+            return false
+        end
+    end
+    for key in pairs(b) do
+        if a[key] == nil then
+            return false
+        end
+    end
+    return true
+end
+
+-- Must match the layout in src/binary.rs
+local function encodeBinary(data)
+    local keyframe = frames_since_keyframe >= KEYFRAME_INTERVAL
+    if keyframe then
+        frames_since_keyframe = 0
+    else
+        frames_since_keyframe = frames_since_keyframe + 1
+    end
+
+    local g, cam = data.g or {}, data.cam
+    local nan = 0 / 0
+    local missing = { x = nan, y = nan, z = nan }
+    local position, velocity = data.position or missing, data.velocity or missing
+    local fixed = {
+        data.time, data.ias, data.mach, data.alt, data.rad_alt, data.pitch, data.bank, data.yaw, data.aoa,
+        g.x, g.y, g.z,
+        cam.x.x, cam.x.y, cam.x.z, cam.y.x, cam.y.y, cam.y.z, cam.z.x, cam.z.y, cam.z.z, cam.p.x, cam.p.y, cam.p.z,
+        position.x, position.y, position.z,
+        velocity.x, velocity.y, velocity.z,
+    }
+    local encoded = {}
+    -- Missing values leave holes in the table, so its length can't be relied on
+    for i = 1, 30 do
+        encoded[i] = encodeFloat(fixed[i])
+    end
+
+    local flags = 0
+    local sections = ""
+    local cp_params = data.cp_params or ""
+    if keyframe or cp_params ~= last_cp_params then
+        flags = flags + 1
+        sections = sections..encodeSection(cp_params)
+        last_cp_params = cp_params
+    end
+    -- The tables are collected anew every frame, so the last ones can be kept for comparison
+    local details = {
+        engine_data = data.engine_data,
+        weapons = data.weapons,
+        targets = data.targets,
+        wingmen = data.wingmen,
+        datalink = data.datalink,
+        unit = data.unit,
+        coalition = data.coalition,
+        view = data.view,
+        dead = data.dead,
+    }
+    if keyframe or not sameValue(details, last_details) then
+        flags = flags + 2
+        sections = sections..encodeSection(json:encode(details))
+        last_details = details
+    end
+
+    return string.char(flags)..encodeU32(data.seq or 0)..table.concat(encoded)..sections
+end
+
+-- Makes the next binary frame include every section, for a new client
+local function resetKeyframe()
+    frames_since_keyframe = KEYFRAME_INTERVAL
+end
+
+local function encodeData(seq)
+    local data = collectData(seq)
+    if ENCODING == "binary" then
+        return encodeBinary(data)
+    end
+    return json:encode(data)
+end
+
+-- Messages are separated by line breaks in JSON, but binary ones need their length in front
+local function frameMessage(message)
+    if ENCODING == "binary" then
+        return encodeU32(#message)..message
+    end
+    return message.."\n"
 end
 
 local function disconnect()
@@ -80,6 +371,86 @@ local function disconnect()
     log_info("Disconnected")
 end
 
+local function acceptClient(connection)
+    client = connection
+    client:settimeout(nil)
+    log_info("Connected")
+    resetKeyframe()
+    client:send(frameMessage(encodeHello()))
+end
+
+-- Clients must send the shared secret on its own line before getting any data, if there is one.
+-- The line can take several frames to arrive, so it is read without blocking the game.
+local function authenticate()
+    local line, err, partial = pending:receive("*l", pending_input)
+    local incomplete = line == nil and err == "timeout" and #partial <= #SECRET
+    if incomplete and socket.gettime() < pending_deadline then
+        pending_input = partial
+        return
+    end
+    if line == SECRET then
+        acceptClient(pending)
+    else
+        log_error("Rejected connection with wrong secret"..(err and (": "..err) or ""))
+        pending:close()
+    end
+    pending = nil
+end
+
+-- Applications using UDP subscribe by sending the shared secret, and keep doing so to stay
+-- subscribed. Each subscription is answered with the greeting, in case an earlier one got lost
+local function receiveSubscriptions()
+    while true do
+        local datagram, ip, port = udp:receivefrom()
+        if datagram == nil then
+            return
+        end
+        local ok, message = pcall(json.decode, json, datagram)
+        if ok and type(message) == "table" and message.subscribe == SECRET then
+            if subscriber == nil or subscriber.ip ~= ip or subscriber.port ~= port then
+                log_info("Subscribed "..ip..":"..port)
+                subscriber = { ip = ip, port = port }
+                resetKeyframe()
+            end
+            subscriber.last_seen = socket.gettime()
+            udp:sendto(encodeHello(), ip, port)
+        else
+            log_error("Rejected subscription from "..tostring(ip))
+        end
+    end
+end
+
+-- Sending never waits, so a datagram the network can't take right away is lost instead of
+-- holding up the game
+local function sendDatagram()
+    if socket.gettime() - subscriber.last_seen > SUBSCRIPTION_TIMEOUT then
+        log_info("Subscription expired")
+        subscriber = nil
+        return
+    end
+    sequence = sequence + 1
+    local _, err = udp:sendto(encodeData(sequence), subscriber.ip, subscriber.port)
+    if err ~= nil and err ~= "timeout" then
+        log_error("Error sending datagram: "..err)
+    end
+end
+
+local function exportDue()
+    if EXPORT_RATE <= 0 then
+        return true
+    end
+    local now = socket.gettime()
+    if now < next_export then
+        return false
+    end
+    next_export = next_export + 1 / EXPORT_RATE
+    -- Don't try to catch up after a slow frame
+    if next_export < now then
+        next_export = now
+    end
+    return true
+end
+
 local upstreamLuaExportStart = LuaExportStart
 local upstreamLuaExportStop = LuaExportStop
 local upstreamLuaExportAfterNextFrame = LuaExportAfterNextFrame
@@ -96,8 +467,20 @@ end
 function LuaExportStart()
     callUpstream(upstreamLuaExportStart, "LuaExportStart")
     log_info("Started")
+    if TRANSPORT == "udp" then
+        udp = socket.udp()
+        local _, err = udp:setsockname(HOST, PORT)
+        if err ~= nil then
+            log_error("Could not bind UDP socket: "..err)
+            udp = nil
+            return
+        end
+        udp:settimeout(0)
+        log_info("Waiting for UDP subscriptions")
+        return
+    end
     server = socket.tcp()
-    server:bind("127.0.0.1", 28561)
+    server:bind(HOST, PORT)
     local _, err = server:listen(1)
     if err ~= nil then
         log_error("Could not listen for connections: "..err)
@@ -114,23 +497,46 @@ function LuaExportStop()
     if client ~= nil then
         disconnect()
     end
+    if pending ~= nil then
+        pending:close()
+        pending = nil
+    end
+    if udp ~= nil then
+        udp:close()
+        udp = nil
+        subscriber = nil
+    end
     log_info("Stopped")
 end
 
 function LuaExportAfterNextFrame()
     callUpstream(upstreamLuaExportAfterNextFrame, "LuaExportAfterNextFrame")
-    if client == nil and server ~= nil then
-        local err
-        client, err = server:accept()
+    if udp ~= nil then
+        receiveSubscriptions()
+        if subscriber ~= nil and exportDue() then
+            sendDatagram()
+        end
+        return
+    end
+    if client == nil and pending == nil and server ~= nil then
+        local connection, err = server:accept()
         if err ~= nil and err ~= "timeout" then
             log_error("Failed to accept connection: "..err)
         end
-        if client ~= nil then
-            log_info("Connected")
+        if connection ~= nil and SECRET == "" then
+            acceptClient(connection)
+        elseif connection ~= nil then
+            connection:settimeout(0)
+            pending = connection
+            pending_input = ""
+            pending_deadline = socket.gettime() + AUTH_TIMEOUT
         end
     end
-    if client ~= nil then
-        local _, err = exportData()
+    if pending ~= nil then
+        authenticate()
+    end
+    if client ~= nil and exportDue() then
+        local _, err = client:send(frameMessage(encodeData(nil)))
         if err ~= nil then
             log_error("Error sending message: "..err)
             disconnect()
@@ -0,0 +1,201 @@
+use crossbeam::scope;
+use raqote::DrawTarget;
+use std::cell::RefCell;
+use std::net::TcpListener;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::{
+    config::{load_or_create_config, Config, ConfigHandle},
+    data::{ApplicationState, FlightDataSamples, LinkStatus},
+    drawing::load_default_font,
+    installer::DCSVersion,
+    recording::{run_replay_worker, DataSource, Recorder, Recording, ReplayControl},
+    relay::{run_relay_server, RelayServer},
+    windows::{self, hmd_window, run_window_loop, show_message_box, MessageBoxType},
+    worker::run_data_worker,
+};
+
+fn set_panic_handler() {
+    let default_panic_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        default_panic_hook(panic_info);
+        let default_message = String::from("Unspecified error");
+        let message_string = panic_info.payload().downcast_ref::<String>();
+        let message_str = panic_info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|&message| String::from(message));
+        let message = match &message_string {
+            Some(message) => message,
+            None => match &message_str {
+                Some(message) => message,
+                None => &default_message,
+            },
+        };
+        let location = match panic_info.location() {
+            Some(location) => format!(
+                "in \"{}\" at line {}:\n\n",
+                location.file(),
+                location.line()
+            ),
+            None => String::new(),
+        };
+        windows::show_message_box(MessageBoxType::Error(format!(
+            "Fatal error {}{}",
+            location, message
+        )));
+        std::process::exit(1);
+    }));
+}
+
+/// Runs the HMD and control windows until the control window is closed
+pub fn run_application(args: &[String]) {
+    set_panic_handler();
+
+    let data_source = match DataSource::from_args(args) {
+        Ok(data_source) => data_source,
+        Err(err) => {
+            show_message_box(MessageBoxType::Error(format!("{}", err)));
+            return;
+        }
+    };
+
+    println!(
+        "Detected DCS paths:\n  Openbeta: {:?}\n  Stable: {:?}",
+        DCSVersion::Stable.user_folder(),
+        DCSVersion::Openbeta.user_folder()
+    );
+
+    // Pre-load the font embedded in the program
+    let default_font = load_default_font();
+
+    // Use an atomic boolean to syncronize the quit flag across threads
+    let quit_signal = AtomicBool::new(false);
+
+    // Get the application configuration and its watcher + notifier combo
+    // Note: we have to keep the watcher around even if we don't use it, or else it will be dropped and stop working
+    let config = match load_or_create_config() {
+        Ok(config) => config,
+        Err(err) if err.downcast_ref::<toml::de::Error>().is_some() => {
+            show_message_box(MessageBoxType::Error(format!(
+                "Error while loading config file:\n\n{}",
+                err
+            )));
+            Config::default()
+        }
+        Err(err) => {
+            eprintln!(
+                "Internal error while loading/creating config file: {:?}",
+                err
+            );
+            Config::default()
+        }
+    };
+
+    let screen_dimensions = windows::get_screen_dimensions();
+    println!("Main display size: {:?}", screen_dimensions);
+
+    // Put the config in an Arc<Mutex<T>> for sharing between threads
+    let config: ConfigHandle = Arc::new(Mutex::new(config));
+
+    // Pin the data to make sure the pointer we use later (in window_proc) can't point to a dropped value
+    let state = Box::pin(ApplicationState {
+        flight_data: RwLock::new(FlightDataSamples::default()),
+        link_status: Arc::new(RwLock::new(LinkStatus::Disconnected)),
+        radar_memory: RwLock::new(Default::default()),
+        draw_target: RefCell::new(DrawTarget::new(screen_dimensions.0, screen_dimensions.1)),
+        font: RefCell::new(default_font),
+        config: Arc::clone(&config),
+        screen_dimensions,
+    });
+
+    // Open the telemetry files before starting any threads, so errors can be shown right away
+    let (recorder, replay) = match &data_source {
+        DataSource::Live { record: None } => (None, None),
+        DataSource::Live { record: Some(path) } => match Recorder::create(path) {
+            Ok(recorder) => (Some(recorder), None),
+            Err(err) => {
+                show_message_box(MessageBoxType::Error(format!("{:?}", err)));
+                return;
+            }
+        },
+        DataSource::Replay(path, options) => match Recording::load(path) {
+            Ok(recording) => (None, Some((recording, options))),
+            Err(err) => {
+                show_message_box(MessageBoxType::Error(format!("{:?}", err)));
+                return;
+            }
+        },
+    };
+    let replay_control = Arc::new(ReplayControl::default());
+
+    // Let other programs subscribe to the flight data, if enabled
+    let relay = RelayServer::default();
+    let relay_listener = {
+        let config = config.lock().unwrap();
+        let relay_config = &config.relay;
+        if relay_config.enabled {
+            match TcpListener::bind((relay_config.host.as_str(), relay_config.port)) {
+                Ok(listener) => Some(listener),
+                Err(err) => {
+                    show_message_box(MessageBoxType::Error(format!(
+                        "Could not start the relay on port {}:\n\n{}",
+                        relay_config.port, err
+                    )));
+                    None
+                }
+            }
+        } else {
+            None
+        }
+    };
+
+    // Use crossbeam's thread scope feature to keep lifetimes tidy as the worker threads don't need to run beyond the main thread
+    let data_handle = &state.flight_data;
+    let radar_handle = &state.radar_memory;
+    let link_handle = &state.link_status;
+    let thread_scope = scope(|scope| {
+        if let Some(listener) = relay_listener {
+            scope.spawn(|_| run_relay_server(&relay, listener, &quit_signal));
+        }
+
+        // Create the worker thread
+        match &replay {
+            Some((recording, options)) => scope.spawn(|_| {
+                run_replay_worker(
+                    recording,
+                    options,
+                    &replay_control,
+                    data_handle,
+                    radar_handle,
+                    &quit_signal,
+                )
+            }),
+            None => scope.spawn(|_| {
+                run_data_worker(
+                    &config,
+                    data_handle,
+                    radar_handle,
+                    link_handle,
+                    recorder,
+                    &relay,
+                    &quit_signal,
+                )
+            }),
+        };
+
+        // Create the two windows
+        let control_window = windows::control_window::create().unwrap();
+        let _hmd_window = hmd_window::create(&state, control_window.hwnd());
+        control_window.set_config(Some(Arc::clone(&config)));
+        control_window.update_install_status();
+        control_window.set_link_status(Some(Arc::clone(&state.link_status)));
+        if replay.is_some() {
+            control_window.set_replay(Some(Arc::clone(&replay_control)));
+        }
+        run_window_loop(control_window.hwnd(), &quit_signal);
+    });
+
+    thread_scope.expect("Error caught in worker thread");
+}
@@ -0,0 +1,68 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::time::Instant;
+
+use crate::{binary, data::FlightData, snapshot::read_file};
+
+pub static USAGE: &str = "Usage: dcs-hemmecs benchmark [--frames COUNT] [--data FILE.json]";
+
+fn measure(
+    name: &str,
+    size: usize,
+    frames: u32,
+    mut parse: impl FnMut() -> Result<FlightData>,
+) -> Result<()> {
+    let start = Instant::now();
+    for _ in 0..frames {
+        parse()?;
+    }
+    let per_frame = start.elapsed() / frames;
+    println!(
+        "{:<18} {:>7} bytes {:>9.2} µs per frame",
+        name,
+        size,
+        per_frame.as_secs_f64() * 1e6
+    );
+    Ok(())
+}
+
+/// Entry point of the `benchmark` subcommand, which compares how long each encoding of the
+/// telemetry takes to parse
+pub fn run(args: &[String]) -> Result<()> {
+    let mut frames = 10000;
+    let mut data = FlightData::sample();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                frames = args
+                    .next()
+                    .and_then(|frames| frames.parse().ok())
+                    .filter(|&frames| frames > 0)
+                    .ok_or_else(|| anyhow!(USAGE))?;
+            }
+            "--data" => {
+                let path = args.next().ok_or_else(|| anyhow!(USAGE))?;
+                data = serde_json::from_slice(&read_file(path)?)
+                    .context(format!("invalid flight data in {:?}", path))?;
+            }
+            _ => bail!(USAGE),
+        }
+    }
+
+    let json = serde_json::to_string(&data)?;
+    let keyframe = binary::encode(&data, true)?;
+    let delta = binary::encode(&data, false)?;
+    println!("Parsing {} frames of each encoding", frames);
+    measure("json", json.len(), frames, || {
+        Ok(serde_json::from_str(&json)?)
+    })?;
+    let mut decoder = binary::Decoder::default();
+    measure("binary, keyframe", keyframe.len(), frames, || {
+        decoder.decode(&keyframe)
+    })?;
+    measure("binary, unchanged", delta.len(), frames, || {
+        decoder.decode(&delta)
+    })?;
+    Ok(())
+}
@@ -0,0 +1,310 @@
+//! Stand-in for DCS running `HemmecsExport.lua`, which serves synthetic flight data for
+//! testing the application without the game
+
+#[path = "../checksum.rs"]
+mod checksum;
+
+use serde_json::{json, Value};
+use std::f32::consts::PI;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+static USAGE: &str = "Usage: dcs-sim [--udp] [level-turn|climb|look-around|targets|eject]";
+
+/// Default address of `LuaExportStart` in HemmecsExport.lua
+const ADDRESS: &str = "127.0.0.1:28561";
+const FRAME_RATE: f32 = 60.0;
+/// 350 kt
+const SPEED: f32 = 180.0;
+const START_ALTITUDE: f32 = 6000.0;
+/// Time between the two positions the velocity is worked out from, in seconds
+const VELOCITY_STEP: f32 = 0.1;
+/// Served as if it were installed with the default connection settings apart from the transport,
+/// so the application accepts the greeting
+static EXPORT_SCRIPT: &str = include_str!("../../lua/Scripts/HemmecsExport.lua");
+/// Same as `SUBSCRIPTION_TIMEOUT` in HemmecsExport.lua
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy)]
+enum Scenario {
+    LevelTurn,
+    Climb,
+    LookAround,
+    Targets,
+    Eject,
+}
+
+impl Scenario {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "level-turn" => Some(Scenario::LevelTurn),
+            "climb" => Some(Scenario::Climb),
+            "look-around" => Some(Scenario::LookAround),
+            "targets" => Some(Scenario::Targets),
+            "eject" => Some(Scenario::Eject),
+            _ => None,
+        }
+    }
+}
+
+/// Aircraft and head attitude, in radians, and position, in meters
+#[derive(Default)]
+struct State {
+    pitch: f32,
+    bank: f32,
+    yaw: f32,
+    head_pitch: f32,
+    head_yaw: f32,
+    position: glm::Vec3,
+    g: f32,
+    ejected: bool,
+}
+
+fn simulate(scenario: Scenario, time: f32) -> State {
+    let straight_and_level = State {
+        position: glm::vec3(SPEED * time, START_ALTITUDE, 0.0),
+        g: 1.0,
+        ..Default::default()
+    };
+    match scenario {
+        Scenario::LevelTurn => {
+            // 3 degrees per second, standard rate turn
+            let turn_rate = f32::to_radians(3.0);
+            let radius = SPEED / turn_rate;
+            let yaw = (time * turn_rate) % (PI * 2.0);
+            State {
+                bank: (SPEED * turn_rate / 9.81).atan(),
+                yaw,
+                position: glm::vec3(
+                    radius * yaw.sin(),
+                    START_ALTITUDE,
+                    radius * (1.0 - yaw.cos()),
+                ),
+                g: 1.0 / (SPEED * turn_rate / 9.81).atan().cos(),
+                ..straight_and_level
+            }
+        }
+        Scenario::Climb => {
+            let pitch = f32::to_radians(15.0);
+            State {
+                pitch,
+                position: glm::vec3(
+                    SPEED * pitch.cos() * time,
+                    START_ALTITUDE + SPEED * pitch.sin() * time,
+                    0.0,
+                ),
+                ..straight_and_level
+            }
+        }
+        Scenario::LookAround => State {
+            head_yaw: f32::to_radians(120.0) * (time / 4.0).sin(),
+            head_pitch: f32::to_radians(40.0) * (time / 3.0).sin(),
+            ..straight_and_level
+        },
+        Scenario::Targets => State {
+            // Look slightly above the HUD, where the targets are
+            head_pitch: f32::to_radians(8.0),
+            ..straight_and_level
+        },
+        Scenario::Eject => State {
+            ejected: time > 10.0,
+            ..straight_and_level
+        },
+    }
+}
+
+fn vec3(vec: &glm::Vec3) -> Value {
+    json!({ "x": vec.x, "y": vec.y, "z": vec.z })
+}
+
+fn position(basis: &[glm::Vec3; 3], p: &glm::Vec3) -> Value {
+    json!({ "x": vec3(&basis[0]), "y": vec3(&basis[1]), "z": vec3(&basis[2]), "p": vec3(p) })
+}
+
+/// Orientation of the camera, as the reverse of `FlightData::camera_angles`
+fn camera_basis(state: &State) -> [glm::Vec3; 3] {
+    let rotate = |basis: [glm::Vec3; 3], angle: f32, axis: &glm::Vec3| {
+        [
+            glm::rotate_vec3(&basis[0], angle, axis),
+            glm::rotate_vec3(&basis[1], angle, axis),
+            glm::rotate_vec3(&basis[2], angle, axis),
+        ]
+    };
+    let x_yaw = glm::rotate_vec3(&glm::Vec3::x_axis(), -state.yaw, &glm::Vec3::y_axis());
+    let z_yaw = glm::rotate_vec3(&glm::Vec3::z_axis(), -state.yaw, &glm::Vec3::y_axis());
+    let x_yaw_pitch = glm::rotate_vec3(&x_yaw, state.pitch, &z_yaw);
+
+    let head = [glm::Vec3::x(), glm::Vec3::y(), glm::Vec3::z()];
+    let head = rotate(head, state.head_pitch, &glm::Vec3::z());
+    let head = rotate(head, -state.head_yaw, &glm::Vec3::y());
+
+    let cam = rotate(head, -state.yaw, &glm::Vec3::y());
+    let cam = rotate(cam, state.pitch, &z_yaw);
+    rotate(cam, state.bank, &x_yaw_pitch)
+}
+
+/// Targets flying head-on towards us, which appear after 5 seconds and get locked after 10
+fn targets(scenario: Scenario, time: f32, state: &State) -> Value {
+    match scenario {
+        Scenario::Targets if time > 5.0 => {
+            let basis = [glm::vec3(-1.0, 0.0, 0.0), glm::Vec3::y(), glm::Vec3::z()];
+            let targets = [(1001, 0.0), (1002, 1500.0)]
+                .iter()
+                .map(|&(id, offset)| {
+                    let p = glm::vec3(40000.0 - SPEED * time, START_ALTITUDE + 1500.0, offset);
+                    json!({
+                        "ID": id,
+                        "position": position(&basis, &p),
+                        "distance": glm::distance(&p, &state.position),
+                        "start_of_lock": if id == 1001 && time > 10.0 { 10.0 } else { 0.0 },
+                        "coalition": 1,
+                    })
+                })
+                .collect();
+            Value::Array(targets)
+        }
+        _ => json!([]),
+    }
+}
+
+fn frame(scenario: Scenario, time: f32) -> Value {
+    let state = simulate(scenario, time);
+    let velocity =
+        (simulate(scenario, time + VELOCITY_STEP).position - state.position) / VELOCITY_STEP;
+    let ejection = if state.ejected { 0.5 } else { -1.0 };
+    let master_arm = matches!(scenario, Scenario::Targets) as i32;
+    json!({
+        "cp_params": format!(
+            "EJECTION_INITIATED_0:{}\nMASTER_ARM:{}\nGEAR_POS:0\nCANOPY_POS:0\n",
+            ejection, master_arm
+        ),
+        "time": time,
+        "ias": SPEED,
+        "mach": SPEED / 316.0,
+        "alt": state.position.y,
+        "rad_alt": state.position.y - 200.0,
+        "pitch": state.pitch,
+        "bank": state.bank,
+        "yaw": state.yaw,
+        "aoa": 2.5 * state.g,
+        "g": { "x": 0.0, "y": state.g, "z": 0.0 },
+        "cam": position(&camera_basis(&state), &state.position),
+        "engine_data": {
+            "RPM": { "left": 85.0, "right": 85.0 },
+            "fuel_internal": 5000.0 - time,
+            "fuel_external": 0.0,
+        },
+        "weapons": {
+            "current": { "name": "AIM-120C", "count": 4 },
+            "shells": 940,
+        },
+        "targets": targets(scenario, time, &state),
+        "wingmen": [],
+        "datalink": [],
+        "unit": "F-15C",
+        "position": vec3(&state.position),
+        "velocity": vec3(&velocity),
+        "view": "normal",
+        "dead": false,
+        "coalition": 2,
+    })
+}
+
+/// Greeting sent by the export script when the connection is made, with the same protocol version
+/// and Adler-32 script hash it would report
+fn hello(transport: &str) -> Value {
+    let script = EXPORT_SCRIPT.replacen(
+        "local TRANSPORT = \"tcp\"",
+        &format!("local TRANSPORT = {:?}", transport),
+        1,
+    );
+    let protocol = script
+        .lines()
+        .find_map(|line| line.strip_prefix("local PROTOCOL_VERSION = "))
+        .and_then(|version| version.trim().parse::<u32>().ok())
+        .expect("PROTOCOL_VERSION not found in HemmecsExport.lua");
+    let script_hash = checksum::adler32(script.as_bytes());
+    json!({ "hello": { "protocol": protocol, "script_hash": script_hash } })
+}
+
+fn serve(stream: TcpStream, scenario: Scenario) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut stream = io::BufWriter::new(stream);
+    writeln!(stream, "{}", hello("tcp"))?;
+    let start = Instant::now();
+    loop {
+        let line = frame(scenario, start.elapsed().as_secs_f32());
+        writeln!(stream, "{}", line)?;
+        stream.flush()?;
+        sleep(Duration::from_secs_f32(1.0 / FRAME_RATE));
+    }
+}
+
+/// Sends a datagram per frame to whoever subscribed last, like the export script does with
+/// `TRANSPORT = "udp"`
+fn serve_udp(scenario: Scenario) -> io::Result<()> {
+    let socket = UdpSocket::bind(ADDRESS)?;
+    socket.set_nonblocking(true)?;
+    println!("Waiting for subscriptions on {}", ADDRESS);
+    let start = Instant::now();
+    let mut subscriber: Option<(SocketAddr, Instant)> = None;
+    let mut seq = 0;
+    let mut buf = [0; 1024];
+    loop {
+        // Accept any subscription, since there is no secret to check, and answer it with the
+        // greeting like the export script does
+        while let Ok((_, address)) = socket.recv_from(&mut buf) {
+            if subscriber.map(|(subscribed, _)| subscribed) != Some(address) {
+                println!("Subscribed {}", address);
+            }
+            subscriber = Some((address, Instant::now()));
+            let _ = socket.send_to(hello("udp").to_string().as_bytes(), address);
+        }
+        match subscriber {
+            Some((_, renewed)) if renewed.elapsed() > SUBSCRIPTION_TIMEOUT => {
+                println!("Subscription expired");
+                subscriber = None;
+            }
+            Some((address, _)) => {
+                seq += 1;
+                let mut datagram = frame(scenario, start.elapsed().as_secs_f32());
+                datagram["seq"] = json!(seq);
+                // Like the export script, ignore any errors and carry on with the next frame
+                let _ = socket.send_to(datagram.to_string().as_bytes(), address);
+            }
+            None => (),
+        }
+        sleep(Duration::from_secs_f32(1.0 / FRAME_RATE));
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut udp = false;
+    let mut scenario = Scenario::LevelTurn;
+    for arg in std::env::args().skip(1) {
+        match Scenario::from_name(&arg) {
+            _ if arg == "--udp" => udp = true,
+            Some(chosen) => scenario = chosen,
+            None => {
+                eprintln!("{}", USAGE);
+                std::process::exit(1);
+            }
+        }
+    }
+    if udp {
+        return serve_udp(scenario);
+    }
+
+    let listener = TcpListener::bind(ADDRESS)?;
+    println!("Listening on {}", ADDRESS);
+    for stream in listener.incoming() {
+        println!("Connected");
+        // Errors just mean the application disconnected, so wait for it to come back
+        if let Err(err) = serve(stream?, scenario) {
+            println!("Disconnected: {}", err);
+        }
+    }
+    Ok(())
+}
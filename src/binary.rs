@@ -0,0 +1,301 @@
+use anyhow::{bail, ensure, Result};
+use serde_json::{Map, Value};
+use std::convert::TryInto;
+#[cfg(windows)]
+use std::io::{self, ErrorKind, Read};
+
+use crate::data::{dcs, FlightData};
+
+/// Largest message accepted, so a corrupted length can't make the application run out of memory
+#[cfg(windows)]
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Flags telling which of the sections that follow the fixed fields are in a message
+const CP_PARAMS_SECTION: u8 = 1;
+const DETAILS_SECTION: u8 = 2;
+
+/// Number of `f32` values at the start of every message
+const FIXED_FIELDS: usize = 30;
+
+/// Fields of `FlightData` which are sent as JSON in the details section, in the same order as
+/// `encodeBinary` in HemmecsExport.lua
+const DETAIL_FIELDS: [&str; 9] = [
+    "engine_data",
+    "weapons",
+    "targets",
+    "wingmen",
+    "datalink",
+    "unit",
+    "coalition",
+    "view",
+    "dead",
+];
+
+/// Reads the payload of the next length-prefixed message from a stream into `buf`, returning
+/// `false` once the stream is closed
+#[cfg(windows)]
+pub fn read_message(reader: &mut impl Read, buf: &mut Vec<u8>) -> io::Result<bool> {
+    let mut length = [0; 4];
+    match reader.read_exact(&mut length) {
+        Ok(_) => (),
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+        Err(err) => return Err(err),
+    }
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("message of {} bytes is too large", length),
+        ));
+    }
+    buf.resize(length, 0);
+    reader.read_exact(buf)?;
+    Ok(true)
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        ensure!(self.buf.len() >= length, "message ended unexpectedly");
+        let (bytes, rest) = self.buf.split_at(length);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn section(&mut self) -> Result<&'a str> {
+        let length = self.u32()? as usize;
+        Ok(std::str::from_utf8(self.take(length)?)?)
+    }
+}
+
+fn vec3(values: &[f32]) -> dcs::Vec3 {
+    dcs::Vec3 {
+        x: values[0],
+        y: values[1],
+        z: values[2],
+    }
+}
+
+/// Reads the sequence number of a message without decoding the rest of it
+pub fn sequence(payload: &[u8]) -> Option<u32> {
+    let mut reader = Reader { buf: payload };
+    reader.u8().ok()?;
+    // The export script starts counting from 1
+    reader.u32().ok().filter(|&seq| seq > 0)
+}
+
+/// Decodes messages in the binary encoding, where the variable-length sections are only sent
+/// when they change, so they have to be remembered between messages
+#[derive(Default)]
+pub struct Decoder {
+    cp_params: Option<String>,
+    details: FlightData,
+}
+
+impl Decoder {
+    /// Decodes the payload of a message, without its length prefix
+    pub fn decode(&mut self, payload: &[u8]) -> Result<FlightData> {
+        let mut reader = Reader { buf: payload };
+        let flags = reader.u8()?;
+        // The sequence number is read by `sequence`
+        reader.u32()?;
+        let mut fixed = [0.0; FIXED_FIELDS];
+        for value in fixed.iter_mut() {
+            *value = reader.f32()?;
+        }
+        let cp_params = if flags & CP_PARAMS_SECTION != 0 {
+            Some(reader.section()?)
+        } else {
+            None
+        };
+        let details = if flags & DETAILS_SECTION != 0 {
+            Some(serde_json::from_str(reader.section()?)?)
+        } else {
+            None
+        };
+        ensure!(
+            reader.buf.is_empty(),
+            "{} unexpected bytes at the end of the message",
+            reader.buf.len()
+        );
+
+        // Only remember the sections once the whole message is known to be valid
+        if let Some(cp_params) = cp_params {
+            self.cp_params = Some(String::from(cp_params)).filter(|params| !params.is_empty());
+        }
+        if let Some(details) = details {
+            self.details = details;
+        }
+
+        Ok(FlightData {
+            cp_params: self.cp_params.clone(),
+            time: fixed[0],
+            ias: fixed[1],
+            mach: fixed[2],
+            alt: fixed[3],
+            rad_alt: fixed[4],
+            pitch: fixed[5],
+            bank: fixed[6],
+            yaw: fixed[7],
+            aoa: fixed[8],
+            g: vec3(&fixed[9..12]),
+            cam: dcs::Position {
+                x: vec3(&fixed[12..15]),
+                y: vec3(&fixed[15..18]),
+                z: vec3(&fixed[18..21]),
+                p: vec3(&fixed[21..24]),
+            },
+            // Sent as NaN when there is no aircraft
+            position: Some(vec3(&fixed[24..27])).filter(|position| !position.x.is_nan()),
+            velocity: Some(vec3(&fixed[27..30])).filter(|velocity| !velocity.x.is_nan()),
+            seq: sequence(payload),
+            ..self.details.clone()
+        })
+    }
+}
+
+/// Encodes a frame the way the export script does, including the variable-length sections only
+/// if `full` is set, as if they had not changed since the last frame
+pub fn encode(data: &FlightData, full: bool) -> Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    payload.push(if full {
+        CP_PARAMS_SECTION | DETAILS_SECTION
+    } else {
+        0
+    });
+    payload.extend_from_slice(&data.seq.unwrap_or(0).to_le_bytes());
+    let cam = &data.cam;
+    let missing = dcs::Vec3 {
+        x: f32::NAN,
+        y: f32::NAN,
+        z: f32::NAN,
+    };
+    let position = data.position.clone().unwrap_or_else(|| missing.clone());
+    let velocity = data.velocity.clone().unwrap_or(missing);
+    let fixed = [
+        data.time,
+        data.ias,
+        data.mach,
+        data.alt,
+        data.rad_alt,
+        data.pitch,
+        data.bank,
+        data.yaw,
+        data.aoa,
+        data.g.x,
+        data.g.y,
+        data.g.z,
+        cam.x.x,
+        cam.x.y,
+        cam.x.z,
+        cam.y.x,
+        cam.y.y,
+        cam.y.z,
+        cam.z.x,
+        cam.z.y,
+        cam.z.z,
+        cam.p.x,
+        cam.p.y,
+        cam.p.z,
+        position.x,
+        position.y,
+        position.z,
+        velocity.x,
+        velocity.y,
+        velocity.z,
+    ];
+    for value in fixed.iter() {
+        payload.extend_from_slice(&value.to_le_bytes());
+    }
+    if full {
+        let mut fields = match serde_json::to_value(data)? {
+            Value::Object(fields) => fields,
+            _ => bail!("flight data is not an object"),
+        };
+        let details = DETAIL_FIELDS
+            .iter()
+            .filter_map(|&field| Some((String::from(field), fields.remove(field)?)))
+            .collect::<Map<String, Value>>();
+        let sections = [
+            data.cp_params.clone().unwrap_or_default(),
+            serde_json::to_string(&details)?,
+        ];
+        for section in sections.iter() {
+            payload.extend_from_slice(&(section.len() as u32).to_le_bytes());
+            payload.extend_from_slice(section.as_bytes());
+        }
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> FlightData {
+        FlightData {
+            cp_params: Some(String::from("EJECTION_INITIATED_0:-1\n")),
+            seq: Some(42),
+            ..FlightData::sample()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = sample();
+        let mut decoder = Decoder::default();
+        let decoded = decoder.decode(&encode(&data, true).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&data).unwrap()
+        );
+
+        // Frames without sections reuse the ones from the last keyframe
+        let next = FlightData {
+            ias: 200.0,
+            seq: Some(43),
+            ..sample()
+        };
+        let decoded = decoder.decode(&encode(&next, false).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&next).unwrap()
+        );
+    }
+
+    #[test]
+    fn sequence_without_decoding() {
+        assert_eq!(sequence(&encode(&sample(), false).unwrap()), Some(42));
+        assert_eq!(sequence(&[0, 1]), None);
+    }
+
+    #[test]
+    fn invalid_messages_keep_sections() {
+        let data = sample();
+        let mut decoder = Decoder::default();
+        decoder.decode(&encode(&data, true).unwrap()).unwrap();
+
+        let mut truncated = encode(&FlightData::default(), true).unwrap();
+        truncated.pop();
+        assert!(decoder.decode(&truncated).is_err());
+
+        let decoded = decoder.decode(&encode(&data, false).unwrap()).unwrap();
+        assert_eq!(decoded.unit, data.unit);
+        assert_eq!(decoded.cp_params, data.cp_params);
+    }
+}
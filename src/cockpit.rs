@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+/// Names of the parameters with their own accessors, which not every aircraft reports
+const EJECTION_INITIATED: &str = "EJECTION_INITIATED";
+const CANOPY_POSITION: &str = "CANOPY_POS";
+const MASTER_ARM: &str = "MASTER_ARM";
+const GEAR_POSITION: &str = "GEAR_POS";
+const NVG: &str = "NVG_ON";
+
+/// A single value from `list_cockpit_params()`, which is either a number or a quoted string
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamValue {
+    Number(f32),
+    Text(String),
+}
+
+impl ParamValue {
+    fn parse(value: &str) -> Self {
+        let value = value.trim();
+        match value.parse() {
+            Ok(number) => ParamValue::Number(number),
+            Err(_) => ParamValue::Text(String::from(value.trim_matches('"'))),
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f32> {
+        match self {
+            ParamValue::Number(number) => Some(*number),
+            ParamValue::Text(_) => None,
+        }
+    }
+
+    /// Switches are reported as numbers, where anything above one half counts as on
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            ParamValue::Number(number) => Some(*number > 0.5),
+            ParamValue::Text(text) if text == "true" => Some(true),
+            ParamValue::Text(text) if text == "false" => Some(false),
+            ParamValue::Text(_) => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ParamValue::Number(_) => None,
+            ParamValue::Text(text) => Some(text),
+        }
+    }
+}
+
+/// Every parameter from the undocumented `list_cockpit_params()` of DCS, which has one
+/// `KEY:value` pair per line
+#[derive(Debug, Clone, Default)]
+pub struct CockpitParams {
+    values: HashMap<String, ParamValue>,
+}
+
+impl CockpitParams {
+    pub fn parse(raw: &str) -> Self {
+        let values = raw
+            .lines()
+            .filter_map(|line| {
+                // Strings can contain colons too, so only split on the first one
+                let mut key_value = line.splitn(2, ':');
+                let key = key_value.next()?.trim();
+                let value = key_value.next()?;
+                if key.is_empty() {
+                    None
+                } else {
+                    Some((String::from(key), ParamValue::parse(value)))
+                }
+            })
+            .collect();
+        CockpitParams { values }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&ParamValue> {
+        self.values.get(key)
+    }
+
+    /// Returns a parameter which exists once per crew member or system, such as `KEY_0`
+    pub fn indexed(&self, key: &str, index: usize) -> Option<&ParamValue> {
+        self.get(&format!("{}_{}", key, index))
+    }
+
+    pub fn number(&self, key: &str) -> Option<f32> {
+        self.get(key).and_then(ParamValue::as_number)
+    }
+
+    pub fn bool(&self, key: &str) -> Option<bool> {
+        self.get(key).and_then(ParamValue::as_bool)
+    }
+
+    // Kept for the aircraft specific parameters, even though none of the built-in ones are strings
+    #[allow(dead_code)]
+    pub fn text(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(ParamValue::as_text)
+    }
+
+    /// Whether the pilot has ejected or died
+    pub fn ejected(&self) -> bool {
+        // (Undocumented) values:
+        // -1: not ejected yet
+        // (0, 1): ejection in progress
+        // 0: ejection finished or pilot dead
+        self.indexed(EJECTION_INITIATED, 0)
+            .and_then(ParamValue::as_number)
+            .map_or(false, |value| value >= 0.0)
+    }
+
+    /// How far the canopy is open, from 0 (closed) to 1 (fully open)
+    pub fn canopy_position(&self) -> Option<f32> {
+        self.number(CANOPY_POSITION)
+    }
+
+    pub fn master_arm(&self) -> Option<bool> {
+        self.bool(MASTER_ARM)
+    }
+
+    /// How far the landing gear is extended, from 0 (up) to 1 (down and locked)
+    pub fn gear_position(&self) -> Option<f32> {
+        self.number(GEAR_POSITION)
+    }
+
+    pub fn nvg(&self) -> Option<bool> {
+        self.bool(NVG)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CockpitParams, ParamValue};
+
+    #[test]
+    fn parses_numbers_and_strings() {
+        let params = CockpitParams::parse("GEAR_POS:1.000000\nBASE_SENSOR:\"HUD\"\nUNIT:F-15C\n");
+        assert_eq!(params.number("GEAR_POS"), Some(1.0));
+        assert_eq!(params.text("BASE_SENSOR"), Some("HUD"));
+        assert_eq!(params.text("UNIT"), Some("F-15C"));
+        assert_eq!(params.number("BASE_SENSOR"), None);
+        assert_eq!(params.text("GEAR_POS"), None);
+    }
+
+    #[test]
+    fn values_can_contain_colons() {
+        let params = CockpitParams::parse("CLOCK:\"12:34:56\"\n");
+        assert_eq!(params.text("CLOCK"), Some("12:34:56"));
+    }
+
+    #[test]
+    fn parses_booleans() {
+        let params = CockpitParams::parse("MASTER_ARM:1.000000\nNVG_ON:false\nA:true\nB:0.2\nC:on");
+        assert_eq!(params.master_arm(), Some(true));
+        assert_eq!(params.nvg(), Some(false));
+        assert_eq!(params.bool("A"), Some(true));
+        assert_eq!(params.bool("B"), Some(false));
+        assert_eq!(params.bool("C"), None);
+    }
+
+    #[test]
+    fn finds_indexed_keys() {
+        let params =
+            CockpitParams::parse("EJECTION_INITIATED_0:-1.000000\nEJECTION_INITIATED_1:0.5\n");
+        assert_eq!(
+            params.indexed("EJECTION_INITIATED", 1),
+            Some(&ParamValue::Number(0.5))
+        );
+        assert_eq!(params.indexed("EJECTION_INITIATED", 2), None);
+        assert!(!params.ejected());
+        assert!(CockpitParams::parse("EJECTION_INITIATED_0:0.000000").ejected());
+    }
+
+    #[test]
+    fn skips_blank_and_malformed_lines() {
+        let params = CockpitParams::parse("\n   \nNO_VALUE\n:1.0\nCANOPY_POS:0.25\n\n");
+        assert_eq!(params.canopy_position(), Some(0.25));
+        assert_eq!(params.get("NO_VALUE"), None);
+        assert_eq!(params.get(""), None);
+    }
+}
@@ -1,10 +1,17 @@
+#[cfg(windows)]
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(windows)]
 use std::fs::{rename, File};
+#[cfg(windows)]
 use std::io::{ErrorKind, Read, Write};
+#[cfg(windows)]
 use std::sync::{Arc, Mutex};
 
+#[cfg(windows)]
 use crate::consts::CONFIG_FILE;
+use crate::units::{AltitudeUnit, DistanceUnit, FuelUnit, SpeedUnit};
 
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(default)]
@@ -27,6 +34,179 @@ impl Default for Occlusion {
     }
 }
 
+impl Occlusion {
+    /// Whether the camera is looking through the HUD, with angles in degrees relative to the nose
+    pub fn in_hud_cone(&self, pitch: f32, yaw: f32) -> bool {
+        pitch < self.hud_vertical_angle && yaw.abs() < self.hud_horizontal_angle
+    }
+}
+
+/// How the HMD is shown in a given situation, ordered from least to most restrictive
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisibilityMode {
+    Show,
+    Dim,
+    Hide,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Visibility {
+    /// How far the camera can be from the aircraft before it counts as an external view, in meters
+    pub cockpit_distance: f32,
+    pub external_view: VisibilityMode,
+    pub map_view: VisibilityMode,
+    pub ejected: VisibilityMode,
+    /// After the aircraft has been destroyed
+    pub dead: VisibilityMode,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility {
+            cockpit_distance: 20.0,
+            external_view: VisibilityMode::Hide,
+            map_view: VisibilityMode::Hide,
+            ejected: VisibilityMode::Hide,
+            dead: VisibilityMode::Hide,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Projection {
+    /// Horizontal field of view of the game in degrees, since DCS doesn't export it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fov: Option<f32>,
+    /// How far to move the symbols in pixels, to the right and down
+    pub offset: (f32, f32),
+    /// How much to spread the symbols out from the center of the screen
+    pub scale: f32,
+}
+
+impl Projection {
+    /// Moves a point which was projected onto the screen by the calibrated offset and scale
+    pub fn adjust(&self, point: (f32, f32), screen_dimensions: (i32, i32)) -> (f32, f32) {
+        let center = (
+            screen_dimensions.0 as f32 / 2.0,
+            screen_dimensions.1 as f32 / 2.0,
+        );
+        (
+            center.0 + (point.0 - center.0) * self.scale + self.offset.0,
+            center.1 + (point.1 - center.1) * self.scale + self.offset.1,
+        )
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection {
+            fov: None,
+            offset: (0.0, 0.0),
+            scale: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ladder {
+    /// Whether to draw the horizon line and pitch ladder
+    pub enabled: bool,
+    /// Degrees of pitch between the rungs
+    pub spacing: f32,
+    /// How many degrees of the ladder to draw above and below where the camera is looking
+    pub extent: f32,
+    /// How far the camera can look away from the nose before the ladder is hidden, in degrees
+    pub max_look_angle: f32,
+}
+
+impl Default for Ladder {
+    fn default() -> Self {
+        Ladder {
+            enabled: true,
+            spacing: 5.0,
+            extent: 20.0,
+            max_look_angle: 60.0,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlightPathMarker {
+    /// Whether to draw where the aircraft is going, as opposed to where its nose points
+    pub enabled: bool,
+    /// Space to leave between the marker and the edge of the screen when it is off to the side,
+    /// in pixels
+    pub edge_margin: f32,
+}
+
+impl Default for FlightPathMarker {
+    fn default() -> Self {
+        FlightPathMarker {
+            enabled: true,
+            edge_margin: 48.0,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TargetLocator {
+    /// Whether to point towards locked targets which are off the screen
+    pub enabled: bool,
+}
+
+impl Default for TargetLocator {
+    fn default() -> Self {
+        TargetLocator { enabled: true }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeadingTape {
+    /// Whether to draw a scrolling heading tape in place of the `heading` field of the layout
+    pub enabled: bool,
+    /// Whether the tape shows where the camera is looking instead of where the aircraft points
+    pub helmet_heading: bool,
+}
+
+impl Default for HeadingTape {
+    fn default() -> Self {
+        HeadingTape {
+            enabled: true,
+            helmet_heading: false,
+        }
+    }
+}
+
+/// Lines of the data block drawn next to locked targets
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TargetData {
+    pub range: bool,
+    /// How fast the range is shrinking
+    pub closure: bool,
+    /// Angle from the tail of the target to the aircraft, and which side of the target it is on
+    pub aspect: bool,
+    pub altitude: bool,
+}
+
+impl Default for TargetData {
+    fn default() -> Self {
+        TargetData {
+            range: true,
+            closure: true,
+            aspect: true,
+            altitude: true,
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Appearance {
@@ -43,18 +223,268 @@ impl Default for Appearance {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Radar {
+    /// How long (in seconds) to keep drawing a target after it was last seen
+    pub memory_time: f32,
+    /// Whether to classify targets as friendly or hostile based on their coalition
+    pub identify_targets: bool,
+}
+
+impl Default for Radar {
+    fn default() -> Self {
+        Radar {
+            memory_time: 4.0,
+            identify_targets: true,
+        }
+    }
+}
+
+/// A piece of flight data which can be placed on the HMD
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    /// Replaced by the heading tape while it is enabled
+    Heading,
+    Ias,
+    Alt,
+    RadAlt,
+    Mach,
+    G,
+    Aoa,
+    Fuel,
+    Weapon,
+    Gun,
+    /// "ARM" while the master arm switch is on
+    MasterArm,
+    /// "GEAR" while the landing gear is not fully up
+    Gear,
+    /// "CANOPY" while the canopy is not fully closed
+    Canopy,
+    /// "NVG" while night vision goggles are on
+    Nvg,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Default for Align {
+    fn default() -> Self {
+        Align::Left
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LayoutItem {
+    pub field: Field,
+    /// Text row counting from the top, or from the bottom if negative
+    #[serde(default)]
+    pub row: i32,
+    #[serde(default)]
+    pub align: Align,
+    /// Text shown before the value, separated by a space
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl LayoutItem {
+    pub fn new(field: Field, row: i32, align: Align, label: Option<&str>) -> Self {
+        LayoutItem {
+            field,
+            row,
+            align,
+            label: label.map(String::from),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    pub fields: Vec<LayoutItem>,
+}
+
+impl Default for Layout {
+    /// The original Hemmecs layout
+    fn default() -> Self {
+        Layout {
+            fields: vec![
+                LayoutItem::new(Field::Heading, 0, Align::Center, None),
+                LayoutItem::new(Field::Ias, 1, Align::Left, None),
+                LayoutItem::new(Field::Alt, 1, Align::Right, None),
+                LayoutItem::new(Field::Mach, -3, Align::Left, Some("M")),
+                LayoutItem::new(Field::Weapon, -3, Align::Right, None),
+                LayoutItem::new(Field::G, -2, Align::Left, Some("G")),
+                LayoutItem::new(Field::Gun, -2, Align::Right, Some("GUN")),
+                LayoutItem::new(Field::Aoa, -1, Align::Left, Some("a")),
+                LayoutItem::new(Field::Fuel, -1, Align::Right, None),
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitMode {
+    /// Use the units of the aircraft's instruments
+    FollowAircraft,
+    /// Always use the units chosen in the config
+    Custom,
+}
+
+/// Units for a single airframe, where any missing values are taken from the global choice
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnitOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<SpeedUnit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<AltitudeUnit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fuel: Option<FuelUnit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<DistanceUnit>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Units {
+    pub mode: UnitMode,
+    pub speed: SpeedUnit,
+    pub altitude: AltitudeUnit,
+    pub fuel: FuelUnit,
+    pub distance: DistanceUnit,
+    /// Per-airframe overrides, keyed by the aircraft name (ie. "F-15C")
+    pub aircraft: BTreeMap<String, UnitOverrides>,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Units {
+            mode: UnitMode::FollowAircraft,
+            speed: SpeedUnit::Knots,
+            altitude: AltitudeUnit::Feet,
+            fuel: FuelUnit::Pounds,
+            distance: DistanceUnit::NauticalMiles,
+            aircraft: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    /// One stream where every frame arrives in order, but DCS waits for the application to read it
+    Tcp,
+    /// One datagram per frame, which can get lost but never holds up DCS
+    Udp,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// One JSON object per frame, which is easy to inspect
+    Json,
+    /// Numbers in binary, with everything else only sent again when it changes
+    Binary,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Connection {
+    /// Address the export script listens on, which can be "0.0.0.0" to allow other computers
+    pub host: String,
+    pub port: u16,
+    pub transport: Transport,
+    pub encoding: Encoding,
+    /// Most frames the export script sends per second, or 0 to send one every time DCS draws one
+    pub export_rate: u32,
+    /// Address of the computer running DCS, when showing the HMD on another one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_host: Option<String>,
+    /// Password the export script asks for before sending any data, unless empty
+    pub secret: String,
+}
+
+impl Connection {
+    /// Address the application should connect to
+    pub fn server_host(&self) -> &str {
+        match &self.remote_host {
+            Some(remote_host) => remote_host,
+            None if self.host == "0.0.0.0" => "127.0.0.1",
+            None => &self.host,
+        }
+    }
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        Connection {
+            host: String::from("127.0.0.1"),
+            port: 28561,
+            transport: Transport::Tcp,
+            encoding: Encoding::Json,
+            export_rate: 0,
+            remote_host: None,
+            secret: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Relay {
+    /// Whether to re-serve the data received from DCS to other programs
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+}
+
+impl Default for Relay {
+    fn default() -> Self {
+        Relay {
+            enabled: false,
+            host: String::from("127.0.0.1"),
+            port: 28562,
+        }
+    }
+}
+
 #[derive(Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Config {
     pub occlusion: Occlusion,
+    pub visibility: Visibility,
+    pub projection: Projection,
+    pub ladder: Ladder,
+    pub flight_path_marker: FlightPathMarker,
+    pub target_locator: TargetLocator,
+    pub target_data: TargetData,
+    pub heading_tape: HeadingTape,
     pub appearance: Appearance,
+    pub radar: Radar,
+    pub layout: Layout,
+    pub units: Units,
+    pub connection: Connection,
+    pub relay: Relay,
 
     #[serde(skip)]
     pub show_sample_data: bool,
+    /// Draws a reticle over a known point in the world to line the projection up with the game
+    #[serde(skip)]
+    pub calibrating: bool,
 }
 
+#[cfg(windows)]
 pub type ConfigHandle = Arc<Mutex<Config>>;
 
+#[cfg(windows)]
 pub fn load_or_create_config() -> Result<Config> {
     // Try to open an existing config
     match File::open(CONFIG_FILE) {
@@ -102,6 +532,7 @@ pub fn load_or_create_config() -> Result<Config> {
     }
 }
 
+#[cfg(windows)]
 pub fn save_config(config: &Config) -> Result<()> {
     let buf = toml::to_vec(&config)?;
     let tmp_filename = format!("{}.tmp", CONFIG_FILE);
@@ -6,6 +6,7 @@ pub const FONT_SIZE: f32 = 48.0;
 pub const TEXT_OFFSET_X: f32 = -FONT_SIZE / 3.0;
 pub const TEXT_OFFSET_Y: f32 = FONT_SIZE * 5.0 / 6.0;
 pub const TEXT_COLUMNS: i32 = (HUD_WIDTH as f32 / (FONT_SIZE / 2.0)) as i32;
+pub const TEXT_ROWS: i32 = (HUD_HEIGHT as f32 / FONT_SIZE) as i32;
 pub static DEFAULT_FONT: &[u8] = include_bytes!("../fonts/Inconsolata-SemiBold.ttf");
 
 pub static ANTI_ALIASED: DrawOptions = DrawOptions {
@@ -22,4 +23,5 @@ pub fn rgb(rgb: (u8, u8, u8)) -> Source<'static> {
     Color::new(255, rgb.0, rgb.1, rgb.2).into()
 }
 
+#[cfg(windows)]
 pub static CONFIG_FILE: &str = "dcs-hemmecs.toml";
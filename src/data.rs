@@ -1,14 +1,43 @@
+#[cfg(windows)]
 use font_kit::font::Font;
+#[cfg(windows)]
 use raqote::DrawTarget;
-use serde::Deserialize;
-use std::sync::RwLock;
-use std::{cell::RefCell, collections::HashMap};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+#[cfg(windows)]
+use std::{
+    cell::RefCell,
+    sync::{Arc, RwLock},
+};
 
+#[cfg(windows)]
+use crate::config::ConfigHandle;
 use crate::{
-    config::{Config, ConfigHandle, Occlusion},
+    cockpit::CockpitParams,
+    config::{Config, Occlusion, Projection, Visibility, VisibilityMode},
     symbols::{Donor, Identification},
 };
 
+/// Standard FC3 field of view at exactly 50% zoom, for when the config doesn't set one
+const DEFAULT_VERTICAL_FOV: f32 = 50.0;
+
+/// How far ahead of the nose the calibration reticle is drawn, in meters, so it is far enough
+/// away that the position of the pilot's head in the cockpit doesn't matter
+const CALIBRATION_DISTANCE: f32 = 5000.0;
+
+/// How far ahead of the aircraft the flight path marker is placed, in meters
+const FLIGHT_PATH_DISTANCE: f32 = 5000.0;
+
+/// Slowest speed at which the flight path marker is drawn, in meters per second, since the
+/// direction of travel is meaningless while taxiing or parked
+const MIN_FLIGHT_PATH_SPEED: f32 = 5.0;
+
+/// How long DCS can go without sending a frame before the frames on either side are no longer
+/// blended together, in seconds
+const MAX_INTERPOLATION_GAP: f32 = 1.0;
+
 trait ToDegrees {
     fn to_degrees(&self) -> Self;
 }
@@ -23,10 +52,16 @@ impl ToDegrees for (f32, f32, f32) {
     }
 }
 
+/// Blends an angle in radians towards another one, the shortest way around the circle
+fn lerp_angle(a: f32, b: f32, t: f32) -> f32 {
+    let difference = (b - a + PI).rem_euclid(2.0 * PI) - PI;
+    a + difference * t
+}
+
 pub mod dcs {
     use super::*;
 
-    #[derive(Debug, Clone, Default, Deserialize)]
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
     #[serde(default)]
     pub struct Vec3 {
         pub x: f32,
@@ -39,16 +74,46 @@ pub mod dcs {
             glm::Vec3::new(self.x, self.y, self.z)
         }
 
+        /// Blends towards another vector, where `t` goes from 0 to 1
+        pub fn lerp(&self, other: &Vec3, t: f32) -> Vec3 {
+            glm::lerp(&self.as_glm_vec3(), &other.as_glm_vec3(), t).into()
+        }
+
+        /// Returns which way the point is from the center of the view of a camera, or `None` if
+        /// it is straight ahead of the camera or straight behind it
+        pub fn bearing(&self, camera: &Position) -> Option<Bearing> {
+            let relative = self.as_glm_vec3() - camera.p.as_glm_vec3();
+            let forward = glm::dot(&relative, &camera.x.as_glm_vec3());
+            let up = glm::dot(&relative, &camera.y.as_glm_vec3());
+            let right = glm::dot(&relative, &camera.z.as_glm_vec3());
+            let sideways = (right * right + up * up).sqrt();
+            if sideways <= 0.0 {
+                return None;
+            }
+            Some(Bearing {
+                direction: (right / sideways, -up / sideways),
+                off_boresight: sideways.atan2(forward),
+            })
+        }
+
+        /// Returns where the point is on the screen, as seen from a camera with the given vertical
+        /// field of view in radians and adjusted by the calibration, or `None` if it is behind
+        /// the camera
         pub fn project(
             &self,
             screen_dimensions: (i32, i32),
             camera: &Position,
+            fov: f32,
+            calibration: &Projection,
         ) -> Option<(f32, f32)> {
             let cam_pos = camera.p.as_glm_vec3();
             let cam_fwd = camera.x.as_glm_vec3();
             let cam_up = camera.y.as_glm_vec3();
-            // Assume standard FC3 FOV at exactly 50% zoom and a 16:9 aspect ratio
-            let projection = glm::perspective(16.0 / 9.0, f32::to_radians(50.0), 1.0, 10000.0)
+            if glm::dot(&(self.as_glm_vec3() - cam_pos), &cam_fwd) <= 0.0 {
+                return None;
+            }
+            let aspect = screen_dimensions.0 as f32 / screen_dimensions.1 as f32;
+            let projection = glm::perspective(aspect, fov, 1.0, 10000.0)
                 * glm::look_at(&cam_pos, &(cam_pos + cam_fwd * 100.0), &cam_up);
             let projected = glm::project(
                 &self.as_glm_vec3(),
@@ -61,14 +126,24 @@ pub mod dcs {
                     screen_dimensions.1 as f32,
                 ),
             );
-            if projected.z >= 0.0 {
-                Some((projected.x, screen_dimensions.1 as f32 - projected.y))
-            } else {
-                None
-            }
+            Some(calibration.adjust(
+                (projected.x, screen_dimensions.1 as f32 - projected.y),
+                screen_dimensions,
+            ))
         }
     }
 
+    /// Where a point is relative to where the camera is looking, which unlike its projection is
+    /// known even when it is behind the camera
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Bearing {
+        /// Direction of the point on the screen from its center, as a unit vector pointing right
+        /// and down
+        pub direction: (f32, f32),
+        /// Angle between the point and the center of the view, in radians
+        pub off_boresight: f32,
+    }
+
     impl From<glm::Vec3> for Vec3 {
         fn from(vec: glm::Vec3) -> Self {
             Vec3 {
@@ -79,7 +154,7 @@ pub mod dcs {
         }
     }
 
-    #[derive(Debug, Clone, Default, Deserialize)]
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
     #[serde(default)]
     pub struct Position {
         /// Orientation x-vector
@@ -105,16 +180,34 @@ pub mod dcs {
                 p: self.p.clone(),
             }
         }
+
+        /// Blends towards another position, keeping the orientation vectors normalized
+        pub fn lerp(&self, other: &Position, t: f32) -> Self {
+            let blend = |a: &Vec3, b: &Vec3| {
+                let blended = glm::lerp(&a.as_glm_vec3(), &b.as_glm_vec3(), t);
+                if glm::length(&blended) > 0.0 {
+                    glm::normalize(&blended).into()
+                } else {
+                    b.clone()
+                }
+            };
+            Position {
+                x: blend(&self.x, &other.x),
+                y: blend(&self.y, &other.y),
+                z: blend(&self.z, &other.z),
+                p: self.p.lerp(&other.p, t),
+            }
+        }
     }
 
-    #[derive(Debug, Clone, Default, Deserialize)]
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
     #[serde(default)]
     pub struct EngineDetails {
         pub left: f32,
         pub right: f32,
     }
 
-    #[derive(Debug, Clone, Default, Deserialize)]
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
     #[serde(default)]
     pub struct EngineData {
         #[serde(rename = "RPM")]
@@ -129,21 +222,21 @@ pub mod dcs {
         }
     }
 
-    #[derive(Debug, Clone, Default, Deserialize)]
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
     #[serde(default)]
     pub struct WeaponDetails {
         pub name: String,
         pub count: i32,
     }
 
-    #[derive(Debug, Clone, Default, Deserialize)]
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
     #[serde(default)]
     pub struct WeaponData {
         pub current: Option<WeaponDetails>,
         pub shells: i32,
     }
 
-    #[derive(Debug, Clone, Default, Deserialize)]
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
     #[serde(default)]
     pub struct Target {
         #[serde(rename = "ID")]
@@ -151,9 +244,53 @@ pub mod dcs {
         pub position: Position,
         pub distance: f32,
         pub start_of_lock: f32,
+        pub coalition: Option<i32>,
+        pub country: Option<i32>,
     }
 
-    #[derive(Debug, Clone, Default, Deserialize)]
+    /// Off-board contact shared by the flight, as returned by `LoGetWingTargets()`
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct DatalinkContact {
+        #[serde(rename = "ID")]
+        pub id: i32,
+        pub position: Position,
+        pub coalition: Option<i32>,
+        pub country: Option<i32>,
+    }
+
+    #[cfg(windows)]
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    pub struct ExporterInfo {
+        pub protocol: u32,
+        pub script_hash: u32,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ViewType {
+        /// Any view of the 3D world, inside or outside of the cockpit
+        Normal,
+        /// The F10 map
+        Map,
+    }
+
+    /// First message sent by the export script after a connection is made
+    #[cfg(windows)]
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct Hello {
+        pub hello: ExporterInfo,
+    }
+
+    /// Sent by the application over UDP to start receiving frames, and repeated to keep receiving
+    /// them
+    #[cfg(windows)]
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Subscription {
+        pub subscribe: String,
+    }
+
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
     #[serde(default)]
     pub struct Wingman {
         pub wingmen_id: i32,
@@ -161,9 +298,11 @@ pub mod dcs {
     }
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct CockpitParams {
-    pub ejected: bool,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    Cockpit,
+    External,
+    Map,
 }
 
 pub enum UnitSystem {
@@ -171,7 +310,7 @@ pub enum UnitSystem {
     Imperial,
 }
 
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct FlightData {
     pub cp_params: Option<String>,
@@ -190,7 +329,19 @@ pub struct FlightData {
     pub weapons: Option<dcs::WeaponData>,
     pub targets: Vec<dcs::Target>,
     pub wingmen: Vec<Option<dcs::Wingman>>,
+    pub datalink: Vec<dcs::DatalinkContact>,
     pub unit: String,
+    pub coalition: Option<i32>,
+    /// World coordinates of the aircraft
+    pub position: Option<dcs::Vec3>,
+    /// Velocity of the aircraft in world coordinates, in meters per second
+    pub velocity: Option<dcs::Vec3>,
+    pub view: Option<dcs::ViewType>,
+    /// Whether the aircraft no longer exists, after being destroyed
+    pub dead: bool,
+    /// Number of the frame, only sent over UDP to detect lost and reordered datagrams
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u32>,
 }
 
 impl FlightData {
@@ -216,43 +367,119 @@ impl FlightData {
         let Occlusion {
             hide_on_hud,
             hide_in_cockpit,
-            hud_horizontal_angle,
-            hud_vertical_angle,
+            ..
         } = config.occlusion;
 
         // HUD
-        (hide_on_hud && pitch < hud_vertical_angle && yaw.abs() < hud_horizontal_angle) ||
+        (hide_on_hud && config.occlusion.in_hud_cone(pitch, yaw)) ||
         // Front dash
         (hide_in_cockpit && pitch < -20.0 && yaw.abs() / 1.5 + pitch < -10.0) ||
         // Side consoles
         (hide_in_cockpit && pitch < -45.0)
     }
 
+    /// Returns the horizontal field of view of the camera in degrees, for the given aspect ratio
+    pub fn horizontal_fov(config: &Projection, aspect: f32) -> f32 {
+        match config.fov {
+            Some(fov) => fov,
+            None => {
+                2.0 * ((DEFAULT_VERTICAL_FOV.to_radians() / 2.0).tan() * aspect)
+                    .atan()
+                    .to_degrees()
+            }
+        }
+    }
+
+    /// Returns the vertical field of view of the camera in radians, for the given aspect ratio
+    pub fn vertical_fov(config: &Projection, aspect: f32) -> f32 {
+        let fov = Self::horizontal_fov(config, aspect).to_radians();
+        2.0 * ((fov / 2.0).tan() / aspect).atan()
+    }
+
+    /// Returns a point far ahead along the path the aircraft is flying, or `None` if it is not
+    /// moving
+    pub fn flight_path_point(&self) -> Option<dcs::Vec3> {
+        let position = self.position.as_ref()?.as_glm_vec3();
+        let velocity = self.velocity.as_ref()?.as_glm_vec3();
+        if glm::length(&velocity) < MIN_FLIGHT_PATH_SPEED {
+            return None;
+        }
+        Some((position + glm::normalize(&velocity) * FLIGHT_PATH_DISTANCE).into())
+    }
+
+    /// Returns a point in the world to line the projection up with, which is the first locked
+    /// target or otherwise a point straight ahead of the nose of the aircraft
+    pub fn calibration_point(&self) -> Option<dcs::Vec3> {
+        if let Some(target) = self
+            .targets
+            .iter()
+            .find(|target| target.start_of_lock > 0.0)
+        {
+            return Some(target.position.p.clone());
+        }
+        let position = self.position.as_ref()?;
+        let nose = glm::Vec3::new(
+            self.pitch.cos() * self.yaw.cos(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.sin(),
+        );
+        Some((position.as_glm_vec3() + nose * CALIBRATION_DISTANCE).into())
+    }
+
+    /// Tells the cockpit apart from external cameras and the F10 map
+    pub fn camera_view(&self, cockpit_distance: f32) -> View {
+        if self.view == Some(dcs::ViewType::Map) {
+            return View::Map;
+        }
+        match &self.position {
+            Some(position)
+                if glm::distance(&position.as_glm_vec3(), &self.cam.p.as_glm_vec3())
+                    > cockpit_distance =>
+            {
+                View::External
+            }
+            _ => View::Cockpit,
+        }
+    }
+
+    /// Decides how to show the HMD, going with the most restrictive setting that applies
+    pub fn visibility(
+        &self,
+        cockpit_params: &CockpitParams,
+        config: &Visibility,
+    ) -> VisibilityMode {
+        let view = self.camera_view(config.cockpit_distance);
+        let states = [
+            (view == View::External, config.external_view),
+            (view == View::Map, config.map_view),
+            (cockpit_params.ejected(), config.ejected),
+            (self.dead, config.dead),
+        ];
+        states
+            .iter()
+            .filter(|(active, _)| *active)
+            .map(|(_, mode)| *mode)
+            .max()
+            .unwrap_or(VisibilityMode::Show)
+    }
+
     pub fn parse_cockpit_params(&self) -> Option<CockpitParams> {
-        self.cp_params.as_ref().map(|params_raw| {
-            let mut params = CockpitParams::default();
-            // DCS undocumented cockpit param format
-            // Each parameter is separated by a line break,
-            // and is presented in the format Key:Value
-            for param in params_raw.split('\n') {
-                let mut key_value = param.split(':');
-                if let Some(key) = key_value.next() {
-                    if let Some(value) = key_value.next() {
-                        match key {
-                            "EJECTION_INITIATED_0" => {
-                                // (Undocumented) values:
-                                // -1: not ejected yet
-                                // (0, 1): ejection in progress
-                                // 0: ejection finished or pilot dead
-                                params.ejected = value.parse::<f32>().unwrap_or(-1.0) >= 0.0;
-                            }
-                            _ => (),
-                        }
-                    }
+        self.cp_params.as_deref().map(CockpitParams::parse)
+    }
+
+    /// Classifies a contact by comparing its coalition with our own
+    pub fn identify(&self, coalition: Option<i32>) -> Identification {
+        match (self.coalition, coalition) {
+            // Coalition 0 is neutral, which we can't tell apart from an unknown contact
+            (Some(own), Some(other)) if own != 0 && other != 0 => {
+                if own == other {
+                    Identification::Friendly
+                } else {
+                    Identification::Hostile
                 }
             }
-            params
-        })
+            _ => Identification::Unknown,
+        }
     }
 
     pub fn get_unit_system(&self) -> UnitSystem {
@@ -270,6 +497,52 @@ impl FlightData {
         }
     }
 
+    /// Returns the flight data a fraction `t` of the way to the next frame, moving the contacts
+    /// seen in both frames along as well
+    pub fn interpolate(&self, next: &FlightData, t: f32) -> FlightData {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        let mut data = next.clone();
+        data.time = lerp(self.time, next.time);
+        data.ias = lerp(self.ias, next.ias);
+        data.mach = lerp(self.mach, next.mach);
+        data.alt = lerp(self.alt, next.alt);
+        data.rad_alt = lerp(self.rad_alt, next.rad_alt);
+        data.pitch = lerp(self.pitch, next.pitch);
+        data.bank = lerp_angle(self.bank, next.bank, t);
+        data.yaw = lerp_angle(self.yaw, next.yaw, t).rem_euclid(2.0 * PI);
+        data.aoa = lerp(self.aoa, next.aoa);
+        data.g = self.g.lerp(&next.g, t);
+        data.cam = self.cam.lerp(&next.cam, t);
+        let lerp_vec3 = |a: &Option<dcs::Vec3>, b: &Option<dcs::Vec3>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.lerp(b, t)),
+            _ => b.clone(),
+        };
+        data.position = lerp_vec3(&self.position, &next.position);
+        data.velocity = lerp_vec3(&self.velocity, &next.velocity);
+        for target in data.targets.iter_mut() {
+            if let Some(old) = self.targets.iter().find(|old| old.id == target.id) {
+                target.position = old.position.lerp(&target.position, t);
+                target.distance = lerp(old.distance, target.distance);
+            }
+        }
+        for contact in data.datalink.iter_mut() {
+            if let Some(old) = self.datalink.iter().find(|old| old.id == contact.id) {
+                contact.position = old.position.lerp(&contact.position, t);
+            }
+        }
+        for wingman in data.wingmen.iter_mut().flatten() {
+            let old = self
+                .wingmen
+                .iter()
+                .flatten()
+                .find(|old| old.wingmen_id == wingman.wingmen_id);
+            if let Some(old) = old {
+                wingman.wingmen_position = old.wingmen_position.lerp(&wingman.wingmen_position, t);
+            }
+        }
+        data
+    }
+
     pub fn sample() -> Self {
         Self {
             ias: 350.0 / 1.943844,
@@ -294,8 +567,70 @@ impl FlightData {
     }
 }
 
+/// The last two frames received from DCS, so the HMD can be drawn smoothly in between them when
+/// DCS sends them less often than the HMD is repainted
+#[derive(Default)]
+// Only the data worker pushes frames, and it doesn't exist outside of Windows
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct FlightDataSamples {
+    previous: Option<FlightData>,
+    latest: Option<FlightData>,
+    /// When the latest frame arrived
+    received: Option<Instant>,
+    /// How long it took the latest frame to arrive after the previous one
+    interval: Option<Duration>,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+impl FlightDataSamples {
+    pub fn push(&mut self, data: FlightData) {
+        let now = Instant::now();
+        self.interval = self.received.map(|received| now - received);
+        self.previous = self.latest.replace(data);
+        self.received = Some(now);
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Returns the flight data as of now, which trails DCS by one frame so it can be interpolated
+    /// instead of guessed
+    pub fn interpolated(&self) -> Option<FlightData> {
+        let latest = self.latest.as_ref()?;
+        if let (Some(previous), Some(received), Some(interval)) =
+            (&self.previous, self.received, self.interval)
+        {
+            let gap = latest.time - previous.time;
+            // Don't blend across pauses, mission restarts or lost frames
+            if gap > 0.0 && gap <= MAX_INTERPOLATION_GAP && interval.as_secs_f32() > 0.0 {
+                // Blend over the time the frames actually arrived apart, which only matches the
+                // model time between them when the game runs in real time
+                let t = (received.elapsed().as_secs_f32() / interval.as_secs_f32()).min(1.0);
+                return Some(previous.interpolate(latest, t));
+            }
+        }
+        Some(latest.clone())
+    }
+}
+
+/// State of the connection to the DCS exporter
+#[derive(Debug, Clone, Copy, PartialEq)]
+// Only the data worker changes the status, and it doesn't exist outside of Windows
+#[cfg_attr(not(windows), allow(dead_code))]
+pub enum LinkStatus {
+    Disconnected,
+    Connected,
+    /// The exporter keeps sending data which can't be understood
+    ProtocolMismatch,
+    /// The installed export script does not match this version of the application
+    ExporterOutdated,
+}
+
+#[cfg(windows)]
 pub struct ApplicationState {
-    pub flight_data: RwLock<Option<FlightData>>,
+    pub flight_data: RwLock<FlightDataSamples>,
+    pub link_status: Arc<RwLock<LinkStatus>>,
     pub radar_memory: RwLock<RadarMemory>,
     pub draw_target: RefCell<DrawTarget>,
     pub font: RefCell<Font>,
@@ -307,10 +642,101 @@ pub struct ApplicationState {
 pub struct RadarTarget {
     pub id: Option<i32>,
     pub position: Option<dcs::Position>,
+    pub velocity: Option<dcs::Vec3>,
     pub iff: Identification,
     pub src: Donor,
     pub last_seen: f32,
     pub locked: bool,
+    pub stale: bool,
+    /// Range reported by our own radar, in meters
+    pub distance: Option<f32>,
+    /// How fast the range is shrinking, in meters per second
+    pub closure: Option<f32>,
+    /// When the range was last refreshed, which can differ from `last_seen` as the datalink
+    /// moves the target without reporting its range
+    distance_time: f32,
+}
+
+impl RadarTarget {
+    fn new(id: i32, time: f32, position: &dcs::Position, iff: Identification, src: Donor) -> Self {
+        RadarTarget {
+            id: Some(id),
+            position: Some(position.clone()),
+            velocity: None,
+            iff,
+            src,
+            last_seen: time,
+            locked: false,
+            stale: false,
+            distance: None,
+            closure: None,
+            distance_time: time,
+        }
+    }
+
+    /// Refreshes the target with a new sample, estimating its velocity from the previous one
+    fn set_position(&mut self, time: f32, position: &dcs::Position) {
+        if let Some(old) = &self.position {
+            let dt = time - self.last_seen;
+            if dt > 0.0 {
+                let delta = position.p.as_glm_vec3() - old.p.as_glm_vec3();
+                self.velocity = Some((delta / dt).into());
+            }
+        }
+        self.position = Some(position.clone());
+        self.last_seen = time;
+        self.stale = false;
+    }
+
+    /// Refreshes the range to the target, estimating the closure rate from the previous one
+    fn set_distance(&mut self, time: f32, distance: f32) {
+        if let Some(old) = self.distance {
+            let dt = time - self.distance_time;
+            if dt > 0.0 {
+                self.closure = Some((old - distance) / dt);
+            }
+        }
+        self.distance = Some(distance);
+        self.distance_time = time;
+    }
+
+    /// Changes where the target is tracked from, forgetting the range measured by the previous
+    /// source
+    fn set_source(&mut self, src: Donor) {
+        if self.src != src {
+            self.distance = None;
+            self.closure = None;
+        }
+        self.src = src;
+    }
+
+    /// Returns the aspect angle of the target as seen from a point in degrees, from 0 when
+    /// looking at its tail to 180 when it is pointed straight at the point, and whether the point
+    /// is off its right side
+    pub fn aspect(&self, from: &dcs::Vec3) -> Option<(f32, bool)> {
+        let position = self.position.as_ref()?;
+        let line_of_sight = from.as_glm_vec3() - position.p.as_glm_vec3();
+        if glm::length(&line_of_sight) <= 0.0 {
+            return None;
+        }
+        let line_of_sight = glm::normalize(&line_of_sight);
+        let forward = glm::dot(&line_of_sight, &position.x.as_glm_vec3());
+        let right = glm::dot(&line_of_sight, &position.z.as_glm_vec3());
+        Some((
+            180.0 - forward.max(-1.0).min(1.0).acos().to_degrees(),
+            right > 0.0,
+        ))
+    }
+
+    /// Returns the last known target position, moved forward in time by its estimated velocity
+    pub fn predicted_position(&self, time: f32) -> Option<dcs::Vec3> {
+        self.position.as_ref().map(|position| match &self.velocity {
+            Some(velocity) => {
+                (position.p.as_glm_vec3() + velocity.as_glm_vec3() * (time - self.last_seen)).into()
+            }
+            None => position.p.clone(),
+        })
+    }
 }
 
 #[derive(Default, Debug)]
@@ -319,49 +745,319 @@ pub struct RadarMemory {
 }
 
 impl RadarMemory {
-    pub const MAX_AGE: f32 = 0.0;
-
-    pub fn update(&mut self, time: f32) {
-        self.targets
-            .retain(|_, target| time - target.last_seen <= Self::MAX_AGE);
+    /// Forgets targets which have not been seen for longer than `max_age` seconds,
+    /// and marks the ones which were not refreshed on the current frame as stale
+    pub fn update(&mut self, time: f32, max_age: f32) {
+        self.targets.retain(|_, target| {
+            // Also discard targets from the future, in case the mission was restarted
+            time >= target.last_seen && time - target.last_seen <= max_age
+        });
+        for target in self.targets.values_mut() {
+            target.stale = time > target.last_seen;
+        }
     }
 
-    pub fn add_target(&mut self, time: f32, target: &dcs::Target) {
-        self.targets
-            .entry(target.id)
-            .and_modify(|old| {
-                old.position = Some(target.position.clone());
-                old.src = Donor::Ownship;
-                old.last_seen = time;
-                old.locked = target.start_of_lock > 0.0;
-            })
-            .or_insert_with(|| RadarTarget {
-                id: Some(target.id),
-                position: Some(target.position.clone()),
-                iff: Identification::Unknown,
-                src: Donor::Ownship,
-                last_seen: time,
-                locked: target.start_of_lock > 0.0,
-            });
+    pub fn add_target(&mut self, time: f32, target: &dcs::Target, iff: Identification) {
+        let entry = self.targets.entry(target.id).or_insert_with(|| {
+            RadarTarget::new(
+                target.id,
+                time,
+                &target.position,
+                Identification::Unknown,
+                Donor::Ownship,
+            )
+        });
+        entry.set_source(Donor::Ownship);
+        entry.set_distance(time, target.distance);
+        entry.set_position(time, &target.position);
+        entry.iff = iff;
+        entry.locked = target.start_of_lock > 0.0;
     }
 
     pub fn add_wingman(&mut self, time: f32, wingman: &dcs::Wingman) {
-        self.targets
-            .entry(wingman.wingmen_id)
-            .and_modify(|old| {
-                old.position = Some(wingman.wingmen_position.clone());
-                old.iff = Identification::Friendly;
-                old.src = Donor::Ownship;
-                old.last_seen = time;
-                old.locked = false;
-            })
-            .or_insert_with(|| RadarTarget {
-                id: Some(wingman.wingmen_id),
-                position: Some(wingman.wingmen_position.clone()),
-                iff: Identification::Friendly,
-                src: Donor::Datalink,
-                last_seen: time,
-                locked: false,
-            });
+        let entry = self.targets.entry(wingman.wingmen_id).or_insert_with(|| {
+            RadarTarget::new(
+                wingman.wingmen_id,
+                time,
+                &wingman.wingmen_position,
+                Identification::Friendly,
+                Donor::Ownship,
+            )
+        });
+        entry.set_source(Donor::Ownship);
+        entry.set_position(time, &wingman.wingmen_position);
+        entry.iff = Identification::Friendly;
+        entry.locked = false;
+    }
+
+    /// Adds a contact received through the datalink, merging it with our own radar track if it
+    /// was already updated this frame
+    pub fn add_datalink(&mut self, time: f32, contact: &dcs::DatalinkContact, iff: Identification) {
+        let entry = self.targets.entry(contact.id).or_insert_with(|| {
+            RadarTarget::new(
+                contact.id,
+                time,
+                &contact.position,
+                Identification::Unknown,
+                Donor::Datalink,
+            )
+        });
+        // Our own sensors take priority over off-board data
+        if let Donor::Ownship = entry.src {
+            if entry.last_seen >= time {
+                return;
+            }
+        }
+        entry.set_source(Donor::Datalink);
+        entry.set_position(time, &contact.position);
+        entry.iff = iff;
+        entry.locked = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dcs::{Bearing, DatalinkContact, Position, Target, Vec3};
+    use super::{FlightData, FlightDataSamples, RadarMemory, MAX_INTERPOLATION_GAP};
+    use crate::{config::Projection, symbols::Identification};
+    use std::time::{Duration, Instant};
+
+    const SCREEN: (i32, i32) = (1920, 1080);
+    const FOV: f32 = 0.8726646; // 50 degrees
+
+    fn vec3(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    /// Camera at the origin, looking north (x) with up being up (y) and right being east (z)
+    fn camera() -> Position {
+        Position {
+            x: vec3(1.0, 0.0, 0.0),
+            y: vec3(0.0, 1.0, 0.0),
+            z: vec3(0.0, 0.0, 1.0),
+            p: vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn assert_near(actual: Option<(f32, f32)>, expected: (f32, f32)) {
+        let actual = actual.expect("point should be on screen");
+        assert!(
+            (actual.0 - expected.0).abs() < 0.5 && (actual.1 - expected.1).abs() < 0.5,
+            "projected to {:?}, expected {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn point_ahead_is_in_the_center() {
+        let projected =
+            vec3(1000.0, 0.0, 0.0).project(SCREEN, &camera(), FOV, &Projection::default());
+        assert_near(projected, (960.0, 540.0));
+    }
+
+    #[test]
+    fn screen_y_grows_downwards() {
+        let (x, y) = vec3(1000.0, 100.0, 0.0)
+            .project(SCREEN, &camera(), FOV, &Projection::default())
+            .unwrap();
+        assert!((x - 960.0).abs() < 0.5);
+        assert!(y < 540.0);
+    }
+
+    #[test]
+    fn field_of_view_spans_the_screen() {
+        let half_fov = (FOV / 2.0).tan();
+        let aspect = SCREEN.0 as f32 / SCREEN.1 as f32;
+        let top = vec3(1000.0, 1000.0 * half_fov, 0.0).project(
+            SCREEN,
+            &camera(),
+            FOV,
+            &Projection::default(),
+        );
+        assert_near(top, (960.0, 0.0));
+        let right = vec3(1000.0, 0.0, 1000.0 * half_fov * aspect).project(
+            SCREEN,
+            &camera(),
+            FOV,
+            &Projection::default(),
+        );
+        assert_near(right, (1920.0, 540.0));
+    }
+
+    #[test]
+    fn points_behind_the_camera_are_not_projected() {
+        assert!(vec3(-1000.0, 0.0, 0.0)
+            .project(SCREEN, &camera(), FOV, &Projection::default())
+            .is_none());
+        assert!(vec3(-1000.0, 0.0, 200.0)
+            .project(SCREEN, &camera(), FOV, &Projection::default())
+            .is_none());
+    }
+
+    #[test]
+    fn follows_the_camera_position_and_orientation() {
+        // Looking east from somewhere else
+        let camera = Position {
+            x: vec3(0.0, 0.0, 1.0),
+            y: vec3(0.0, 1.0, 0.0),
+            z: vec3(-1.0, 0.0, 0.0),
+            p: vec3(5000.0, 3000.0, -2000.0),
+        };
+        let ahead =
+            vec3(5000.0, 3000.0, -1000.0).project(SCREEN, &camera, FOV, &Projection::default());
+        assert_near(ahead, (960.0, 540.0));
+        // North is to the left when looking east
+        let (x, _) = vec3(5100.0, 3000.0, -1000.0)
+            .project(SCREEN, &camera, FOV, &Projection::default())
+            .unwrap();
+        assert!(x < 960.0);
+    }
+
+    #[test]
+    fn calibration_moves_and_spreads_the_points() {
+        let calibration = Projection {
+            offset: (10.0, -20.0),
+            scale: 2.0,
+            ..Default::default()
+        };
+        let center = vec3(1000.0, 0.0, 0.0).project(SCREEN, &camera(), FOV, &calibration);
+        assert_near(center, (970.0, 520.0));
+        let (x, _) = vec3(1000.0, 0.0, 100.0)
+            .project(SCREEN, &camera(), FOV, &Projection::default())
+            .unwrap();
+        let right = vec3(1000.0, 0.0, 100.0).project(SCREEN, &camera(), FOV, &calibration);
+        assert_near(right, (960.0 + (x - 960.0) * 2.0 + 10.0, 520.0));
+    }
+
+    #[test]
+    fn bearing_points_towards_the_side_of_the_point() {
+        let Bearing {
+            direction,
+            off_boresight,
+        } = vec3(1000.0, 0.0, 1000.0).bearing(&camera()).unwrap();
+        assert!((direction.0 - 1.0).abs() < 1e-6 && direction.1.abs() < 1e-6);
+        assert!((off_boresight.to_degrees() - 45.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn bearing_is_known_behind_the_camera() {
+        let Bearing {
+            direction,
+            off_boresight,
+        } = vec3(-1000.0, 1000.0, 0.0).bearing(&camera()).unwrap();
+        assert!(direction.0.abs() < 1e-6 && (direction.1 + 1.0).abs() < 1e-6);
+        assert!((off_boresight.to_degrees() - 135.0).abs() < 1e-3);
+        assert!(vec3(-1000.0, 0.0, 0.0).bearing(&camera()).is_none());
+    }
+
+    fn target(distance: f32) -> Target {
+        Target {
+            id: 1,
+            position: camera(),
+            distance,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn closure_uses_the_time_of_the_last_range() {
+        let mut memory = RadarMemory::default();
+        memory.add_target(0.0, &target(10000.0), Identification::Unknown);
+        memory.add_target(1.0, &target(9800.0), Identification::Unknown);
+        assert_eq!(memory.targets[&1].closure, Some(200.0));
+
+        // Datalink updates move the target without a range, which is measured again from scratch
+        let contact = DatalinkContact {
+            id: 1,
+            position: camera(),
+            ..Default::default()
+        };
+        memory.add_datalink(2.0, &contact, Identification::Unknown);
+        assert_eq!(memory.targets[&1].distance, None);
+        assert_eq!(memory.targets[&1].closure, None);
+        memory.add_target(3.0, &target(9000.0), Identification::Unknown);
+        memory.add_target(5.0, &target(8000.0), Identification::Unknown);
+        assert_eq!(memory.targets[&1].closure, Some(500.0));
+    }
+
+    fn target_at(id: i32, x: f32) -> Target {
+        Target {
+            id,
+            position: Position {
+                p: vec3(x, 0.0, 0.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn angles_blend_the_shortest_way_around() {
+        let previous = FlightData {
+            yaw: 359f32.to_radians(),
+            bank: 179f32.to_radians(),
+            ..Default::default()
+        };
+        let next = FlightData {
+            yaw: 1f32.to_radians(),
+            bank: -179f32.to_radians(),
+            ..Default::default()
+        };
+        let data = previous.interpolate(&next, 0.5);
+        let yaw = data.yaw.to_degrees();
+        assert!(yaw < 0.01 || yaw > 359.99, "yaw blended to {}", yaw);
+        let bank = data.bank.to_degrees();
+        assert!(
+            (bank.abs() - 180.0).abs() < 0.01,
+            "bank blended to {}",
+            bank
+        );
+    }
+
+    #[test]
+    fn contacts_in_one_frame_are_not_blended() {
+        let previous = FlightData {
+            targets: vec![target_at(1, 0.0), target_at(2, 0.0)],
+            ..Default::default()
+        };
+        let next = FlightData {
+            targets: vec![target_at(1, 100.0), target_at(3, 100.0)],
+            ..Default::default()
+        };
+        let data = previous.interpolate(&next, 0.5);
+        let targets = data
+            .targets
+            .iter()
+            .map(|target| (target.id, target.position.p.x))
+            .collect::<Vec<_>>();
+        // Targets which just disappeared are gone, and new ones appear where they were seen
+        assert_eq!(targets, vec![(1, 50.0), (3, 100.0)]);
+    }
+
+    #[test]
+    fn frames_too_far_apart_are_not_blended() {
+        let samples = |gap: f32| FlightDataSamples {
+            previous: Some(FlightData {
+                time: 10.0,
+                ias: 100.0,
+                ..Default::default()
+            }),
+            latest: Some(FlightData {
+                time: 10.0 + gap,
+                ias: 200.0,
+                ..Default::default()
+            }),
+            received: Some(Instant::now()),
+            interval: Some(Duration::from_secs(60)),
+        };
+        // Just received, so the blend has barely started
+        let ias = samples(MAX_INTERPOLATION_GAP).interpolated().unwrap().ias;
+        assert!(ias < 150.0, "interpolated to {}", ias);
+        let ias = samples(MAX_INTERPOLATION_GAP + 1.0)
+            .interpolated()
+            .unwrap()
+            .ias;
+        assert_eq!(ias, 200.0);
     }
 }
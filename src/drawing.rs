@@ -1,28 +1,69 @@
-use font_kit::font::Font;
+use font_kit::{font::Font, handle::Handle};
 use once_cell::sync::Lazy;
-use raqote::{DrawTarget, Point, Source};
+use raqote::{DrawTarget, PathBuilder, Point, Source, StrokeStyle};
 use regex::Regex;
+use std::sync::Arc;
 
 use crate::{
-    config::Config,
+    cockpit::CockpitParams,
+    config::{Align, Config, Field, TargetData, VisibilityMode},
     consts::{
-        background, rgb, ANTI_ALIASED, FONT_SIZE, HUD_HEIGHT, HUD_WIDTH, TEXT_COLUMNS,
-        TEXT_OFFSET_X, TEXT_OFFSET_Y,
+        background, rgb, ANTI_ALIASED, DEFAULT_FONT, FONT_SIZE, HUD_HEIGHT, HUD_WIDTH,
+        TEXT_COLUMNS, TEXT_OFFSET_X, TEXT_OFFSET_Y, TEXT_ROWS,
     },
-    data::{FlightData, RadarMemory, UnitSystem},
-    symbols::draw_symbol,
+    data::{dcs, FlightData, LinkStatus, RadarMemory, RadarTarget},
+    ladder::draw_ladder,
+    symbols::{
+        draw_flight_path_marker, draw_locator_arrow, draw_reticle, draw_symbol, Identification,
+        ARROW_LENGTH,
+    },
+    units::{display_units, DisplayUnits},
 };
 
+/// Opacity of the HMD when it is dimmed instead of hidden
+const DIM_OPACITY: f32 = 0.3;
+
+/// Where the data block of a locked target goes, relative to the center of its symbol
+const DATA_BLOCK_OFFSET: (f32, f32) = (32.0, -16.0);
+
+/// Degrees of heading shown on the tape, and how wide it is in pixels
+const TAPE_SPAN: f32 = 60.0;
+const TAPE_WIDTH: f32 = 480.0;
+/// Where the tick marks of the tape start, below the labels in the first row of text
+const TAPE_TOP: f32 = FONT_SIZE + 4.0;
+/// Degrees between the tick marks, of which every other one is longer
+const TAPE_TICK_SPACING: f32 = 5.0;
+const TAPE_MINOR_TICK: f32 = 10.0;
+const TAPE_MAJOR_TICK: f32 = 20.0;
+/// Degrees between the labels of the tape
+const TAPE_LABEL_SPACING: i32 = 30;
+const TAPE_STROKE_WIDTH: f32 = 2.0;
+
 static WEAPON_CODE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:\w+[-.])?(\w+)(?:\s.+)?").unwrap());
 
-/// Formats a line as left and right aligned columns of text
-fn two_columns(left: &str, right: &str) -> String {
-    format!(
-        "{left}{right:>padding$}",
-        left = left,
-        right = right,
-        padding = TEXT_COLUMNS as usize - left.len() - 1
-    )
+/// Loads the font embedded in the program
+pub fn load_default_font() -> Font {
+    Handle::from_memory(Arc::new(DEFAULT_FONT.into()), 0)
+        .load()
+        .unwrap()
+}
+
+/// Writes text into a grid of characters at the given row and alignment
+fn place_text(lines: &mut [Vec<char>], row: i32, align: Align, text: &str) {
+    let row = if row < 0 { TEXT_ROWS + row } else { row };
+    if let Some(line) = lines.get_mut(row as usize) {
+        // Leave the last column free, as it is partially cut off
+        let width = TEXT_COLUMNS as usize - 1;
+        let len = text.chars().count().min(width);
+        let start = match align {
+            Align::Left => 0,
+            Align::Center => (TEXT_COLUMNS as usize - len) / 2,
+            Align::Right => width - len,
+        };
+        for (i, c) in text.chars().take(len).enumerate() {
+            line[start + i] = c;
+        }
+    }
 }
 
 /// Draws text with the default font size
@@ -67,81 +108,470 @@ fn draw_text(
     );
 }
 
-fn render_data(data: &FlightData) -> String {
-    // Convert units as necessary
-    let unit_system = data.get_unit_system();
-    let (ias, ias_digits, alt, alt_digits) = match unit_system {
-        // m/s -> km/h and m
-        UnitSystem::Metric => (data.ias * 3.6, 4, data.alt, 5),
-        // m/s -> kn and m -> ft
-        UnitSystem::Imperial => (data.ias * 1.943844, 3, data.alt * 3.28084, 5),
-    };
+/// Returns the width and height of a block of text drawn with `draw_text`
+fn text_size(text: &str) -> (f32, f32) {
+    let columns = text.lines().map(|line| line.chars().count()).max();
+    (
+        columns.unwrap_or(0) as f32 * FONT_SIZE / 2.0,
+        text.lines().count() as f32 * FONT_SIZE,
+    )
+}
+
+/// Returns the offsets for `draw_text` which put the top left corner of the text at a point
+fn text_offsets(top_left: (f32, f32)) -> (f32, f32) {
+    (top_left.0 - TEXT_OFFSET_X - FONT_SIZE / 2.0, top_left.1)
+}
+
+/// Formats the value of a single field, or returns `None` if there is no data for it
+fn render_field(
+    field: Field,
+    data: &FlightData,
+    cockpit_params: &CockpitParams,
+    units: &DisplayUnits,
+) -> Option<String> {
+    match field {
+        Field::Heading => Some(format!("{:0>3.0}", data.yaw.to_degrees())),
+        Field::Ias => Some(format!(
+            "[{0:>1$.0}]",
+            units.speed.convert(data.ias),
+            units.speed.digits()
+        )),
+        Field::Alt => Some(format!("[{:>5.0}]", units.altitude.convert(data.alt))),
+        Field::RadAlt => Some(format!("[{:>5.0}]", units.altitude.convert(data.rad_alt))),
+        Field::Mach => Some(format!("{:.2}", data.mach)),
+        Field::G => Some(format!("{:.1}", data.g.y)),
+        Field::Aoa => Some(format!("{:.1}", data.aoa)),
+        // Total fuel (FC3 only)
+        Field::Fuel => data.engine_data.as_ref().map(|engine_data| {
+            format!(
+                "{:.0} {}",
+                units.fuel.convert(engine_data.total_fuel()),
+                units.fuel.symbol()
+            )
+        }),
+        Field::Weapon => data
+            .weapons
+            .as_ref()
+            .and_then(|weapons| weapons.current.as_ref())
+            .map(|current| {
+                // Remove the prefix and suffix for brevity
+                let short_name = WEAPON_CODE
+                    .captures(&current.name)
+                    .and_then(|captures| captures.get(1))
+                    .map(|m| m.as_str())
+                    .unwrap_or(&current.name);
+
+                format!("{} {}", short_name, current.count)
+            }),
+        Field::Gun => data
+            .weapons
+            .as_ref()
+            .map(|weapons| weapons.shells.to_string()),
+        // Cockpit state, for the aircraft which report it
+        Field::MasterArm => cockpit_params
+            .master_arm()
+            .filter(|&armed| armed)
+            .map(|_| String::from("ARM")),
+        Field::Gear => cockpit_params
+            .gear_position()
+            .filter(|&position| position > 0.0)
+            .map(|_| String::from("GEAR")),
+        Field::Canopy => cockpit_params
+            .canopy_position()
+            .filter(|&position| position > 0.0)
+            .map(|_| String::from("CANOPY")),
+        Field::Nvg => cockpit_params
+            .nvg()
+            .filter(|&on| on)
+            .map(|_| String::from("NVG")),
+    }
+}
+
+fn render_data(data: &FlightData, cockpit_params: &CockpitParams, config: &Config) -> String {
+    let units = display_units(&config.units, data);
+    let mut lines = vec![vec![' '; TEXT_COLUMNS as usize]; TEXT_ROWS as usize];
+
+    for item in &config.layout.fields {
+        if let (Field::Heading, true) = (item.field, config.heading_tape.enabled) {
+            continue;
+        }
+        if let Some(value) = render_field(item.field, data, cockpit_params, &units) {
+            let text = match &item.label {
+                Some(label) => format!("{} {}", label, value),
+                None => value,
+            };
+            place_text(&mut lines, item.row, item.align, &text);
+        }
+    }
+
     // Generate the output text
-    format!(
-        "{}\n{}\n\n\n\n\n\n\n\n\n\n\n\n{}\n{}\n{}",
-        format!("                   {:0>3.0}", data.yaw.to_degrees()),
-        two_columns(
-            &format!("[{0:>1$.0}]", ias, ias_digits),
-            &format!("[{0:>1$.0}]", alt, alt_digits)
-        ),
-        // 3rd line from bottom
-        {
-            let mach_str = format!("M {:.2}", data.mach);
-            if let Some(weapon) = &data.weapons {
-                let weapon_str = if let Some(current) = &weapon.current {
-                    // Remove the prefix and suffix for brevity
-                    let short_name = WEAPON_CODE
-                        .captures(&current.name)
-                        .and_then(|captures| captures.get(1))
-                        .map(|m| m.as_str())
-                        .unwrap_or(&current.name);
-
-                    format!("{} {}", short_name, current.count)
-                } else {
-                    String::new()
-                };
-                two_columns(&mach_str, &weapon_str)
-            } else {
-                mach_str
-            }
-        },
-        // 2nd line from bottom
-        {
-            let g_str = format!("G {:.1}", data.g.y);
-            if let Some(weapon) = &data.weapons {
-                // G-force and cannon ammo
-                let shells_str = format!("GUN {}", weapon.shells);
-                two_columns(&g_str, &shells_str)
-            } else {
-                // Just g-force
-                g_str
-            }
+    lines
+        .iter()
+        .map(|line| line.iter().collect::<String>().trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Formats the data block of a locked target, with one line for each enabled item which is known
+fn render_target_data(
+    config: &TargetData,
+    target: &RadarTarget,
+    ownship: &dcs::Vec3,
+    units: &DisplayUnits,
+) -> String {
+    let mut lines = Vec::new();
+    if let (true, Some(distance)) = (config.range, target.distance) {
+        lines.push(format!(
+            "{:.1}{}",
+            units.distance.convert(distance),
+            units.distance.symbol()
+        ));
+    }
+    if let (true, Some(closure)) = (config.closure, target.closure) {
+        lines.push(format!("Vc {:.0}", units.speed.convert(closure)));
+    }
+    if let (true, Some((aspect, right))) = (config.aspect, target.aspect(ownship)) {
+        lines.push(format!("A {:.0}{}", aspect, if right { "R" } else { "L" }));
+    }
+    if let (true, Some(position)) = (config.altitude, &target.position) {
+        lines.push(format!("{:.0}", units.altitude.convert(position.p.y)));
+    }
+    lines.join("\n")
+}
+
+/// Draws a heading tape across the top of the HUD area, which scrolls with either the heading of
+/// the aircraft or the one the camera is looking at
+fn draw_heading_tape(
+    config: &Config,
+    data: &FlightData,
+    draw_target: &mut DrawTarget,
+    offsets: (f32, f32),
+    default_font: &Font,
+    color: &Source,
+) {
+    let heading = if config.heading_tape.helmet_heading {
+        data.yaw + data.camera_angles().1
+    } else {
+        data.yaw
+    };
+    let heading = heading.to_degrees().rem_euclid(360.0);
+    let center = offsets.0 + HUD_WIDTH as f32 / 2.0;
+    let top = offsets.1 + TAPE_TOP;
+    let scale = TAPE_WIDTH / TAPE_SPAN;
+
+    let mut pb = PathBuilder::new();
+    let first = ((heading - TAPE_SPAN / 2.0) / TAPE_TICK_SPACING).ceil() as i32;
+    let last = ((heading + TAPE_SPAN / 2.0) / TAPE_TICK_SPACING).floor() as i32;
+    for i in first..=last {
+        let tick = i as f32 * TAPE_TICK_SPACING;
+        let x = center + (tick - heading) * scale;
+        let length = if i % 2 == 0 {
+            TAPE_MAJOR_TICK
+        } else {
+            TAPE_MINOR_TICK
+        };
+        pb.move_to(x, top);
+        pb.line_to(x, top + length);
+
+        let label = match (tick.round() as i32).rem_euclid(360) {
+            0 => String::from("N"),
+            90 => String::from("E"),
+            180 => String::from("S"),
+            270 => String::from("W"),
+            degrees if degrees % TAPE_LABEL_SPACING == 0 => format!("{:02}", degrees / 10),
+            _ => continue,
+        };
+        let (width, _) = text_size(&label);
+        let top_left = (x - width / 2.0, offsets.1);
+        draw_text(
+            draw_target,
+            default_font,
+            color,
+            &label,
+            text_offsets(top_left),
+        );
+    }
+
+    // Caret under the middle of the tape, pointing up at the current heading
+    let caret = top + TAPE_MAJOR_TICK + 4.0;
+    pb.move_to(center - TAPE_MINOR_TICK, caret + TAPE_MINOR_TICK);
+    pb.line_to(center, caret);
+    pb.line_to(center + TAPE_MINOR_TICK, caret + TAPE_MINOR_TICK);
+
+    let stroke = StrokeStyle {
+        width: TAPE_STROKE_WIDTH,
+        ..Default::default()
+    };
+    draw_target.stroke(&pb.finish(), color, &stroke, &ANTI_ALIASED);
+}
+
+/// Nicely places the HUD area of the HMD in the center and a little bit high
+fn hud_offsets(screen_dimensions: (i32, i32)) -> (f32, f32) {
+    (
+        screen_dimensions.0 as f32 / 2.0 - HUD_WIDTH as f32 / 2.0,
+        screen_dimensions.1 as f32 / 2.0
+            - HUD_HEIGHT as f32 / 2.0
+            - screen_dimensions.1 as f32 / 10.0,
+    )
+}
+
+/// Draws the flight path marker where the aircraft is heading, or at the edge of the screen
+/// closest to it when that is off to the side
+fn draw_velocity_vector(
+    config: &Config,
+    data: &FlightData,
+    draw_target: &mut DrawTarget,
+    screen_dimensions: (i32, i32),
+    fov: f32,
+    color: &Source,
+) {
+    let marker = &config.flight_path_marker;
+    if !marker.enabled {
+        return;
+    }
+    let point = match data.flight_path_point() {
+        Some(point) => point,
+        None => return,
+    };
+    let center = (
+        screen_dimensions.0 as f32 / 2.0,
+        screen_dimensions.1 as f32 / 2.0,
+    );
+    let (dx, dy) = match point.project(screen_dimensions, &data.cam, fov, &config.projection) {
+        Some((x, y)) => (x - center.0, y - center.1),
+        // Behind the camera only the direction is known, which is always off the screen
+        None => match point.bearing(&data.cam) {
+            Some(bearing) => (
+                bearing.direction.0 * (center.0 + center.1),
+                bearing.direction.1 * (center.0 + center.1),
+            ),
+            None => return,
         },
-        // last line
+    };
+
+    // Pull the marker towards the center of the screen until it is within the margin, so it
+    // still points the right way
+    let limit = ((center.0 - marker.edge_margin).max(0.0) / dx.abs())
+        .min((center.1 - marker.edge_margin).max(0.0) / dy.abs());
+    if limit < 1.0 {
+        let (x, y) = (center.0 + dx * limit, center.1 + dy * limit);
+        draw_flight_path_marker(draw_target, x, y, true, color);
+    } else {
+        let (x, y) = (center.0 + dx, center.1 + dy);
+        draw_flight_path_marker(draw_target, x, y, false, color);
+    }
+}
+
+/// Points towards the locked targets which are behind or beside the camera from the edge of the
+/// HUD area, along with how far they are from the center of the view and their range
+fn draw_target_locator(
+    config: &Config,
+    data: &FlightData,
+    draw_target: &mut DrawTarget,
+    screen_dimensions: (i32, i32),
+    fov: f32,
+    default_font: &Font,
+    color: &Source,
+) {
+    if !config.target_locator.enabled {
+        return;
+    }
+    let (width, height) = (screen_dimensions.0 as f32, screen_dimensions.1 as f32);
+    let offsets = hud_offsets(screen_dimensions);
+    let half = (HUD_WIDTH as f32 / 2.0, HUD_HEIGHT as f32 / 2.0);
+    let center = (offsets.0 + half.0, offsets.1 + half.1);
+    let units = display_units(&config.units, data);
+
+    for target in data
+        .targets
+        .iter()
+        .filter(|target| target.start_of_lock > 0.0)
+    {
+        let point = &target.position.p;
+        let on_screen = point
+            .project(screen_dimensions, &data.cam, fov, &config.projection)
+            .map_or(false, |(x, y)| {
+                x >= 0.0 && x <= width && y >= 0.0 && y <= height
+            });
+        let bearing = match point.bearing(&data.cam) {
+            Some(bearing) if !on_screen => bearing,
+            _ => continue,
+        };
+
+        // The tip of the arrow goes on the edge of the HUD area
+        let (dx, dy) = bearing.direction;
+        let reach = (half.0 / dx.abs()).min(half.1 / dy.abs());
+        let tip = (center.0 + dx * reach, center.1 + dy * reach);
+        draw_locator_arrow(draw_target, tip.0, tip.1, bearing.direction, color);
+
+        // Followed by the text, towards the center
+        let text = format!(
+            "{:.0}°\n{:.1}",
+            bearing.off_boresight.to_degrees(),
+            units.distance.convert(target.distance)
+        );
+        let (text_width, text_height) = text_size(&text);
+        let distance = ARROW_LENGTH + text_width.max(text_height) / 2.0;
+        let top_left = (
+            tip.0 - dx * distance - text_width / 2.0,
+            tip.1 - dy * distance - text_height / 2.0,
+        );
+        draw_text(
+            draw_target,
+            default_font,
+            color,
+            &text,
+            text_offsets(top_left),
+        );
+    }
+}
+
+/// Draws the radar targets and the HMD text for a frame of flight data
+fn draw_flight_data(
+    config: &Config,
+    data: &FlightData,
+    cockpit_params: &CockpitParams,
+    radar_memory: &mut RadarMemory,
+    draw_target: &mut DrawTarget,
+    screen_dimensions: (i32, i32),
+    default_font: &Font,
+) {
+    radar_memory.update(data.time, config.radar.memory_time);
+
+    for wingman in &data.wingmen {
+        if let Some(wingman) = wingman {
+            radar_memory.add_wingman(data.time, wingman);
+        }
+    }
+
+    let identify = |coalition| {
+        if config.radar.identify_targets {
+            data.identify(coalition)
+        } else {
+            Identification::Unknown
+        }
+    };
+
+    for target in &data.targets {
+        radar_memory.add_target(data.time, target, identify(target.coalition));
+    }
+
+    // Datalink contacts go last so they don't override our own radar tracks
+    for contact in &data.datalink {
+        radar_memory.add_datalink(data.time, contact, identify(contact.coalition));
+    }
+
+    let aspect = screen_dimensions.0 as f32 / screen_dimensions.1 as f32;
+    let fov = FlightData::vertical_fov(&config.projection, aspect);
+    let color = rgb(config.appearance.color);
+
+    let units = display_units(&config.units, data);
+    let ownship = data.position.as_ref().unwrap_or(&data.cam.p);
+
+    // Draw radar targets
+    for (_, target) in &radar_memory.targets {
+        if let Some((x, y)) = &target
+            .predicted_position(data.time)
+            .map(|pos| pos.project(screen_dimensions, &data.cam, fov, &config.projection))
+            .flatten()
         {
-            let aoa_str = format!("a {:.1}", data.aoa);
-            if let Some(engine_data) = &data.engine_data {
-                // AoA and total fuel (FC3 only)
-                let fuel_str = match unit_system {
-                    // kg
-                    UnitSystem::Metric => format!("{:.0} kg", engine_data.total_fuel()),
-                    // kg -> lb
-                    UnitSystem::Imperial => {
-                        format!("{:.0} lbs", engine_data.total_fuel() * 2.2046225)
-                    }
-                };
-                two_columns(&aoa_str, &fuel_str)
-            } else {
-                // Just AoA
-                aoa_str
+            draw_symbol(
+                draw_target,
+                *x,
+                *y,
+                &target.iff,
+                &target.src,
+                target.locked,
+                target.stale,
+            );
+            if target.locked && !target.stale {
+                let text = render_target_data(&config.target_data, target, ownship, &units);
+                let top_left = (x + DATA_BLOCK_OFFSET.0, y + DATA_BLOCK_OFFSET.1);
+                draw_text(
+                    draw_target,
+                    default_font,
+                    &color,
+                    &text,
+                    text_offsets(top_left),
+                );
             }
         }
-    )
+    }
+
+    draw_ladder(config, data, draw_target, screen_dimensions, fov, &color);
+    draw_velocity_vector(config, data, draw_target, screen_dimensions, fov, &color);
+    draw_target_locator(
+        config,
+        data,
+        draw_target,
+        screen_dimensions,
+        fov,
+        default_font,
+        &color,
+    );
+
+    // Decide whether to also draw the rest of the HMD data based on if the user is looking at an
+    // occluded area (ie. inside of the cockpit), if they have enabled occlusion
+    if !FlightData::is_occluded(data.camera_angles(), &config) {
+        let text = render_data(&data, cockpit_params, &config);
+        let offsets = hud_offsets(screen_dimensions);
+        draw_text(draw_target, &default_font, &color, &text, offsets);
+        if config.heading_tape.enabled {
+            draw_heading_tape(config, data, draw_target, offsets, default_font, &color);
+        }
+    }
+}
+
+/// Draws the calibration reticle over a known point in the world, along with the current
+/// projection settings in place of the flight data
+fn draw_calibration(
+    config: &Config,
+    data: &FlightData,
+    draw_target: &mut DrawTarget,
+    screen_dimensions: (i32, i32),
+    default_font: &Font,
+    color: &Source,
+) {
+    let calibration = &config.projection;
+    let aspect = screen_dimensions.0 as f32 / screen_dimensions.1 as f32;
+    let fov = FlightData::vertical_fov(calibration, aspect);
+    let reticle = data
+        .calibration_point()
+        .and_then(|point| point.project(screen_dimensions, &data.cam, fov, calibration));
+    if let Some((x, y)) = reticle {
+        draw_reticle(draw_target, x, y, color);
+    }
+
+    let fov_source = match calibration.fov {
+        Some(_) => "CONFIG",
+        None => "DEFAULT",
+    };
+    let text = format!(
+        "CALIBRATION\nFOV {:.1} {}\nOFFSET {:+.0} {:+.0}\nSCALE {:.3}{}",
+        FlightData::horizontal_fov(calibration, aspect),
+        fov_source,
+        calibration.offset.0,
+        calibration.offset.1,
+        calibration.scale,
+        if reticle.is_some() {
+            ""
+        } else {
+            "\n\nNOSE NOT IN VIEW"
+        }
+    );
+    draw_text(
+        draw_target,
+        default_font,
+        color,
+        &text,
+        hud_offsets(screen_dimensions),
+    );
 }
 
 pub fn draw<'a>(
     config: &Config,
     data: &Option<FlightData>,
+    link_status: LinkStatus,
     radar_memory: &mut RadarMemory,
     draw_target: &'a mut DrawTarget,
     screen_dimensions: (i32, i32),
@@ -149,59 +579,69 @@ pub fn draw<'a>(
 ) -> &'a [u32] {
     draw_target.clear(background());
 
-    // Nicely place the HUD area of the HMD in the center and a little bit high
-    let offsets = (
-        screen_dimensions.0 as f32 / 2.0 - HUD_WIDTH as f32 / 2.0,
-        screen_dimensions.1 as f32 / 2.0
-            - HUD_HEIGHT as f32 / 2.0
-            - screen_dimensions.1 as f32 / 10.0,
-    );
+    let offsets = hud_offsets(screen_dimensions);
     let color = rgb(config.appearance.color);
 
     if config.show_sample_data {
-        let sample_data = render_data(&FlightData::sample());
+        let sample = FlightData::sample();
+        let cockpit_params = sample.parse_cockpit_params().unwrap_or_default();
+        let sample_data = render_data(&sample, &cockpit_params, &config);
         draw_text(draw_target, &default_font, &color, &sample_data, offsets);
+        if config.heading_tape.enabled {
+            draw_heading_tape(config, &sample, draw_target, offsets, default_font, &color);
+        }
+    } else if link_status == LinkStatus::ProtocolMismatch {
+        draw_text(
+            draw_target,
+            &default_font,
+            &color,
+            "Protocol Mismatch\nCheck if HemmecsExport.lua\nis up to date",
+            offsets,
+        );
+    } else if link_status == LinkStatus::ExporterOutdated {
+        draw_text(
+            draw_target,
+            &default_font,
+            &color,
+            "Exporter Outdated\nClick Update Hemmecs\nand restart the mission",
+            offsets,
+        );
+    } else if let Some(data) = data.as_ref().filter(|_| config.calibrating) {
+        draw_calibration(
+            config,
+            data,
+            draw_target,
+            screen_dimensions,
+            default_font,
+            &color,
+        );
     } else if let Some(data) = data {
         let cockpit_params = data.parse_cockpit_params().unwrap_or_default();
-
-        // Cancel drawing if the pilot has ejected
-        let text = if cockpit_params.ejected {
-            String::new()
-        } else {
-            radar_memory.update(data.time);
-
-            for wingman in &data.wingmen {
-                if let Some(wingman) = wingman {
-                    radar_memory.add_wingman(data.time, wingman);
-                }
-            }
-
-            for target in &data.targets {
-                radar_memory.add_target(data.time, target);
+        match data.visibility(&cockpit_params, &config.visibility) {
+            VisibilityMode::Show => draw_flight_data(
+                config,
+                data,
+                &cockpit_params,
+                radar_memory,
+                draw_target,
+                screen_dimensions,
+                default_font,
+            ),
+            VisibilityMode::Dim => {
+                draw_target.push_layer(DIM_OPACITY);
+                draw_flight_data(
+                    config,
+                    data,
+                    &cockpit_params,
+                    radar_memory,
+                    draw_target,
+                    screen_dimensions,
+                    default_font,
+                );
+                draw_target.pop_layer();
             }
-
-            // Draw radar targets
-            for (_, target) in &radar_memory.targets {
-                if let Some((x, y)) = &target
-                    .position
-                    .as_ref()
-                    .map(|pos| pos.p.project(screen_dimensions, &data.cam))
-                    .flatten()
-                {
-                    draw_symbol(draw_target, *x, *y, &target.iff, &target.src, target.locked);
-                }
-            }
-
-            // Decide whether to also draw the rest of the HMD data based on if the user is looking at an
-            // occluded area (ie. inside of the cockpit), if they have enabled occlusion
-            if FlightData::is_occluded(data.camera_angles(), &config) {
-                String::new()
-            } else {
-                render_data(&data)
-            }
-        };
-
-        draw_text(draw_target, &default_font, &color, &text, offsets);
+            VisibilityMode::Hide => (),
+        }
     } else {
         draw_text(draw_target, &default_font, &color, "Not Connected", offsets);
     }
@@ -6,6 +6,17 @@ pub static HEMMECS_EXPORT_SCRIPT: &str = include_str!("../../lua/Scripts/Hemmecs
 pub static HEMMECS_EXPORT_SCRIPT_PATH: &str = "Scripts/HemmecsExport.lua";
 pub static DCS_EXPORT_LUA_PATH: &str = "Scripts/Export.lua";
 
+/// Lines of the export script which are replaced with the configured connection settings
+pub static EXPORT_SCRIPT_HOST_LINE: &str = "local HOST = \"127.0.0.1\"";
+pub static EXPORT_SCRIPT_PORT_LINE: &str = "local PORT = 28561";
+pub static EXPORT_SCRIPT_SECRET_LINE: &str = "local SECRET = \"\"";
+pub static EXPORT_SCRIPT_TRANSPORT_LINE: &str = "local TRANSPORT = \"tcp\"";
+pub static EXPORT_SCRIPT_ENCODING_LINE: &str = "local ENCODING = \"json\"";
+pub static EXPORT_SCRIPT_EXPORT_RATE_LINE: &str = "local EXPORT_RATE = 0";
+
+/// Version of the data format sent by the export script, which must match `PROTOCOL_VERSION` in it
+pub static PROTOCOL_VERSION: u32 = 3;
+
 /// Any lines containing both `hemmecsStatus` and `hemmecsErr`, in any order
 pub static HEMMECS_VARS_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r".*hemmecsStatus.*hemmecsErr.*\n|.*:hemmecsErr.*hemmecsStatus.*\n").unwrap()
@@ -1,6 +1,8 @@
 mod constants;
 mod utils;
 
+pub use constants::PROTOCOL_VERSION;
+
 use std::{
     fs::{create_dir_all, remove_file, File},
     io::{Read, Write},
@@ -9,6 +11,11 @@ use std::{
 
 use anyhow::{Context, Result};
 
+use crate::{
+    checksum::adler32,
+    config::{Connection, Encoding, Transport},
+};
+
 pub enum DCSVersion {
     Stable,
     Openbeta,
@@ -30,7 +37,7 @@ impl DCSVersion {
         Ok(utils::saved_games()?.join(folder_name))
     }
 
-    pub fn install_status(&self) -> Result<InstallStatus> {
+    pub fn install_status(&self, connection: &Connection) -> Result<InstallStatus> {
         match Path::is_dir(&self.user_folder()?) {
             false => Ok(InstallStatus::DCSNotFound),
             true => {
@@ -42,7 +49,7 @@ impl DCSVersion {
                 } else if File::open(export_script_path)?
                     .bytes()
                     .map(Result::unwrap)
-                    .eq(constants::HEMMECS_EXPORT_SCRIPT.bytes())
+                    .eq(export_script(connection).bytes())
                 {
                     Ok(InstallStatus::Installed)
                 } else {
@@ -53,18 +60,68 @@ impl DCSVersion {
     }
 }
 
-pub fn install(dcs_version: &DCSVersion) -> Result<()> {
+/// Returns the export script with the configured connection settings filled in
+pub fn export_script(connection: &Connection) -> String {
+    let transport = match connection.transport {
+        Transport::Tcp => "tcp",
+        Transport::Udp => "udp",
+    };
+    let encoding = match connection.encoding {
+        Encoding::Json => "json",
+        Encoding::Binary => "binary",
+    };
+    constants::HEMMECS_EXPORT_SCRIPT
+        .replacen(
+            constants::EXPORT_SCRIPT_HOST_LINE,
+            &format!("local HOST = {:?}", connection.host),
+            1,
+        )
+        .replacen(
+            constants::EXPORT_SCRIPT_PORT_LINE,
+            &format!("local PORT = {}", connection.port),
+            1,
+        )
+        .replacen(
+            constants::EXPORT_SCRIPT_SECRET_LINE,
+            &format!("local SECRET = {:?}", connection.secret),
+            1,
+        )
+        .replacen(
+            constants::EXPORT_SCRIPT_TRANSPORT_LINE,
+            &format!("local TRANSPORT = {:?}", transport),
+            1,
+        )
+        .replacen(
+            constants::EXPORT_SCRIPT_ENCODING_LINE,
+            &format!("local ENCODING = {:?}", encoding),
+            1,
+        )
+        .replacen(
+            constants::EXPORT_SCRIPT_EXPORT_RATE_LINE,
+            &format!("local EXPORT_RATE = {}", connection.export_rate),
+            1,
+        )
+}
+
+/// Adler-32 checksum of the export script, as calculated by the script itself when loaded
+pub fn script_hash(connection: &Connection) -> u32 {
+    adler32(export_script(connection).as_bytes())
+}
+
+pub fn install(dcs_version: &DCSVersion, connection: &Connection) -> Result<()> {
     // Ensure the scripts folder exists
     create_dir_all(dcs_version.user_folder()?.join("Scripts"))
         .context("could not ensure DCS Scripts folder exists")?;
 
     // Overwrite the Hemmecs export script
-    let mut export_script = utils::open_rw(
+    let mut script_file = utils::open_rw(
         &dcs_version
             .user_folder()?
             .join(constants::HEMMECS_EXPORT_SCRIPT_PATH),
     )?;
-    write!(export_script, "{}", constants::HEMMECS_EXPORT_SCRIPT)?;
+    let script = export_script(connection);
+    write!(script_file, "{}", script)?;
+    script_file.set_len(script.len() as u64)?;
 
     // Rewrite the Export.lua snippet
     let mut export_lua = utils::open_rw(
@@ -0,0 +1,121 @@
+use raqote::{DrawTarget, PathBuilder, Source, StrokeStyle};
+
+use crate::{
+    config::Config,
+    consts::ANTI_ALIASED,
+    data::{dcs, FlightData},
+};
+
+/// How far from the camera the ladder is placed, in meters, so it is well within the far plane
+const LADDER_DISTANCE: f32 = 1000.0;
+/// Angles in degrees from the center of the view to either end of each half of a rung
+const RUNG_GAP: f32 = 2.0;
+const RUNG_WIDTH: f32 = 6.0;
+const HORIZON_WIDTH: f32 = 20.0;
+/// Length of the ticks at the ends of the rungs which point towards the horizon, in degrees
+const TICK_LENGTH: f32 = 1.0;
+const STROKE_WIDTH: f32 = 2.0;
+const DASH_LENGTH: f32 = 8.0;
+
+/// Returns a direction at the given pitch and heading, moved sideways by an angle, all in degrees
+fn direction(pitch: f32, heading: f32, side: f32) -> glm::Vec3 {
+    let (pitch, heading) = (pitch.to_radians(), heading.to_radians());
+    let forward = glm::Vec3::new(
+        pitch.cos() * heading.cos(),
+        pitch.sin(),
+        pitch.cos() * heading.sin(),
+    );
+    let right = glm::Vec3::new(-heading.sin(), 0.0, heading.cos());
+    forward + right * side.to_radians().tan()
+}
+
+/// Adds a line through the given points to the path, unless any of them can't be projected
+fn add_line(pb: &mut PathBuilder, points: &[Option<(f32, f32)>]) {
+    if points.iter().any(Option::is_none) {
+        return;
+    }
+    for (i, &(x, y)) in points.iter().flatten().enumerate() {
+        if i == 0 {
+            pb.move_to(x, y);
+        } else {
+            pb.line_to(x, y);
+        }
+    }
+}
+
+/// Draws the horizon line and the rungs of the pitch ladder around where the camera is looking,
+/// as long as it is looking roughly forward but not through the HUD, which has its own
+pub fn draw_ladder(
+    config: &Config,
+    data: &FlightData,
+    draw_target: &mut DrawTarget,
+    screen_dimensions: (i32, i32),
+    fov: f32,
+    color: &Source,
+) {
+    let ladder = &config.ladder;
+    if !ladder.enabled || ladder.spacing <= 0.0 {
+        return;
+    }
+    let (pitch, yaw, _) = data.camera_angles();
+    let (pitch, yaw) = (pitch.to_degrees(), yaw.to_degrees());
+    if pitch.abs() > ladder.max_look_angle
+        || yaw.abs() > ladder.max_look_angle
+        || config.occlusion.in_hud_cone(pitch, yaw)
+    {
+        return;
+    }
+
+    // The rungs stay level with the horizon, centered on the heading the camera is looking at
+    let cam = &data.cam;
+    let heading = cam.x.z.atan2(cam.x.x).to_degrees();
+    let look_pitch = cam.x.y.max(-1.0).min(1.0).asin().to_degrees();
+    let project = |pitch: f32, side: f32| {
+        let point = cam.p.as_glm_vec3() + direction(pitch, heading, side) * LADDER_DISTANCE;
+        dcs::Vec3::from(point).project(screen_dimensions, cam, fov, &config.projection)
+    };
+
+    let solid = StrokeStyle {
+        width: STROKE_WIDTH,
+        ..Default::default()
+    };
+    // Rungs below the horizon are dashed, like on a real HUD
+    let dashed = StrokeStyle {
+        width: STROKE_WIDTH,
+        dash_array: vec![DASH_LENGTH, DASH_LENGTH],
+        ..Default::default()
+    };
+
+    let first = ((look_pitch - ladder.extent) / ladder.spacing).ceil() as i32;
+    let last = ((look_pitch + ladder.extent) / ladder.spacing).floor() as i32;
+    for i in first..=last {
+        let rung_pitch = i as f32 * ladder.spacing;
+        if rung_pitch.abs() >= 90.0 {
+            continue;
+        }
+        let mut pb = PathBuilder::new();
+        for &side in [-1.0, 1.0].iter() {
+            if i == 0 {
+                add_line(
+                    &mut pb,
+                    &[
+                        project(0.0, side * RUNG_GAP),
+                        project(0.0, side * HORIZON_WIDTH),
+                    ],
+                );
+            } else {
+                let tick = -rung_pitch.signum() * TICK_LENGTH;
+                add_line(
+                    &mut pb,
+                    &[
+                        project(rung_pitch, side * RUNG_GAP),
+                        project(rung_pitch, side * RUNG_WIDTH),
+                        project(rung_pitch + tick, side * RUNG_WIDTH),
+                    ],
+                );
+            }
+        }
+        let style = if rung_pitch < 0.0 { &dashed } else { &solid };
+        draw_target.stroke(&pb.finish(), color, style, &ANTI_ALIASED);
+    }
+}
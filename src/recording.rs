@@ -0,0 +1,212 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::{Mutex, RwLock};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::data::{dcs, FlightDataSamples, RadarMemory};
+
+/// Writes the raw telemetry stream to a file, one line per frame, prefixed by the number of
+/// seconds since the recording started and a tab
+pub struct Recorder {
+    file: LineWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).context(format!("cannot create {:?}", path))?;
+        Ok(Recorder {
+            file: LineWriter::new(file),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, line: &str) -> io::Result<()> {
+        writeln!(
+            self.file,
+            "{:.3}\t{}",
+            self.start.elapsed().as_secs_f32(),
+            line
+        )
+    }
+}
+
+pub struct Frame {
+    pub time: f32,
+    pub line: String,
+}
+
+/// A telemetry stream previously saved by a `Recorder`
+pub struct Recording {
+    pub frames: Vec<Frame>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).context(format!("cannot open {:?}", path))?;
+        let mut frames = Vec::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.context(format!("cannot read {:?}", path))?;
+            let mut parts = line.splitn(2, '\t');
+            let time = parts.next().and_then(|time| time.parse().ok());
+            match (time, parts.next()) {
+                // The greeting sent by the exporter is not flight data, so don't replay it
+                (Some(_), Some(line)) if serde_json::from_str::<dcs::Hello>(line).is_ok() => (),
+                (Some(time), Some(line)) => frames.push(Frame {
+                    time,
+                    line: String::from(line),
+                }),
+                _ => eprintln!("Skipping malformed frame at line {} of {:?}", i + 1, path),
+            }
+        }
+        Ok(Recording { frames })
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames.last().map(|frame| frame.time).unwrap_or(0.0)
+    }
+}
+
+pub static USAGE: &str =
+    "Usage: dcs-hemmecs [--record FILE] | dcs-hemmecs replay [--speed SPEED] [--loop] FILE";
+
+pub struct ReplayOptions {
+    /// Playback speed, where 1.0 is real time
+    pub speed: f32,
+    /// Start over when reaching the end of the recording
+    pub looping: bool,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        ReplayOptions {
+            speed: 1.0,
+            looping: false,
+        }
+    }
+}
+
+/// Where the flight data shown on the HMD comes from
+pub enum DataSource {
+    /// Connect to DCS, optionally saving the telemetry stream to a file
+    Live { record: Option<PathBuf> },
+    /// Play back a previously saved telemetry stream
+    Replay(PathBuf, ReplayOptions),
+}
+
+impl DataSource {
+    /// Parses the command line arguments used to start the application
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut args = args.iter();
+        match args.next().map(String::as_str) {
+            None => Ok(DataSource::Live { record: None }),
+            Some("--record") => match (args.next(), args.next()) {
+                (Some(path), None) => Ok(DataSource::Live {
+                    record: Some(PathBuf::from(path)),
+                }),
+                _ => bail!(USAGE),
+            },
+            Some("replay") => {
+                let mut options = ReplayOptions::default();
+                let mut path = None;
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--speed" => {
+                            options.speed = args
+                                .next()
+                                .and_then(|speed| speed.parse().ok())
+                                .filter(|&speed: &f32| speed > 0.0)
+                                .ok_or_else(|| anyhow!(USAGE))?;
+                        }
+                        "--loop" => options.looping = true,
+                        _ if path.is_none() && !arg.starts_with("--") => {
+                            path = Some(PathBuf::from(arg))
+                        }
+                        _ => bail!(USAGE),
+                    }
+                }
+                let path = path.ok_or_else(|| anyhow!(USAGE))?;
+                Ok(DataSource::Replay(path, options))
+            }
+            Some(_) => bail!(USAGE),
+        }
+    }
+}
+
+/// Playback controls shared between the replay worker and the UI
+#[derive(Default)]
+pub struct ReplayControl {
+    pub paused: AtomicBool,
+    seek: Mutex<f32>,
+}
+
+impl ReplayControl {
+    /// Requests the playback position to move by the given amount of seconds
+    pub fn seek_by(&self, seconds: f32) {
+        *self.seek.lock().unwrap() += seconds;
+    }
+
+    fn take_seek(&self) -> f32 {
+        std::mem::replace(&mut *self.seek.lock().unwrap(), 0.0)
+    }
+}
+
+/// Feeds a recording into the flight data and radar memory as if it came from DCS
+pub fn run_replay_worker(
+    recording: &Recording,
+    options: &ReplayOptions,
+    control: &ReplayControl,
+    data_handle: &RwLock<FlightDataSamples>,
+    radar_handle: &RwLock<RadarMemory>,
+    quit_signal: &AtomicBool,
+) {
+    let duration = recording.duration();
+    let mut position = 0.0;
+    let mut next_frame = 0;
+    let mut last_tick = Instant::now();
+    println!("Replaying {:.1} seconds of telemetry", duration);
+
+    while quit_signal.load(Relaxed) == false {
+        let elapsed = last_tick.elapsed().as_secs_f32();
+        last_tick = Instant::now();
+        if control.paused.load(Relaxed) == false {
+            position += elapsed * options.speed;
+        }
+
+        let seek = control.take_seek();
+        let restart = options.looping && position > duration;
+        if seek != 0.0 || restart {
+            position = if restart {
+                0.0
+            } else {
+                (position + seek).max(0.0).min(duration)
+            };
+            // Look for the next frame from the beginning of the recording again
+            next_frame = 0;
+            radar_handle.write().unwrap().targets.clear();
+            data_handle.write().unwrap().clear();
+        }
+
+        // Only the most recent frame is shown, in case several of them were due
+        let mut latest = None;
+        while let Some(frame) = recording.frames.get(next_frame) {
+            if frame.time > position {
+                break;
+            }
+            latest = Some(frame);
+            next_frame += 1;
+        }
+        if let Some(frame) = latest {
+            match serde_json::from_str(&frame.line) {
+                Ok(data) => data_handle.write().unwrap().push(data),
+                Err(err) => eprintln!("Skipping invalid frame at {:.3}s: {}", frame.time, err),
+            }
+        }
+
+        sleep(Duration::from_millis(5));
+    }
+}
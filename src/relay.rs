@@ -0,0 +1,80 @@
+use crossbeam::channel::{bounded, Sender, TrySendError};
+use std::io::{ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+use crate::data::FlightData;
+
+/// How many frames can be waiting for a slow subscriber before new ones are dropped
+const SUBSCRIBER_QUEUE: usize = 8;
+
+/// Re-serves the flight data received from DCS to any number of local programs, as one line of
+/// JSON per frame
+#[derive(Default)]
+pub struct RelayServer {
+    subscribers: Mutex<Vec<Sender<Arc<String>>>>,
+}
+
+impl RelayServer {
+    /// Sends a frame to every subscriber, without waiting for any of them
+    pub fn broadcast(&self, data: &FlightData) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+        let line = match serde_json::to_string(data) {
+            Ok(line) => Arc::new(line),
+            Err(err) => {
+                eprintln!(
+                    "Warning: could not serialize flight data for relay: {}",
+                    err
+                );
+                return;
+            }
+        };
+        subscribers.retain(|subscriber| match subscriber.try_send(Arc::clone(&line)) {
+            Ok(_) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+
+    fn subscribe(&self, mut stream: TcpStream) {
+        let (sender, receiver) = bounded::<Arc<String>>(SUBSCRIBER_QUEUE);
+        self.subscribers.lock().unwrap().push(sender);
+        // The thread ends, dropping the receiver and with it the subscription, once writing fails
+        spawn(move || {
+            for line in receiver {
+                if writeln!(stream, "{}", line).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Accepts relay subscribers until the application quits
+pub fn run_relay_server(relay: &RelayServer, listener: TcpListener, quit_signal: &AtomicBool) {
+    if let Err(err) = listener.set_nonblocking(true) {
+        eprintln!("Warning: relay disabled: {}", err);
+        return;
+    }
+    while quit_signal.load(Relaxed) == false {
+        match listener.accept() {
+            Ok((stream, address)) => {
+                println!("Relay subscriber connected from {}", address);
+                match stream.set_nonblocking(false).and(stream.set_nodelay(true)) {
+                    Ok(_) => relay.subscribe(stream),
+                    Err(err) => eprintln!("Warning: could not set up relay subscriber: {}", err),
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => sleep(Duration::from_millis(100)),
+            Err(err) => {
+                eprintln!("Warning: could not accept relay subscriber: {}", err);
+                sleep(Duration::from_millis(100));
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::{anyhow, bail, Context, Result};
+use font_kit::font::Font;
+use raqote::DrawTarget;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config::Config,
+    data::{FlightData, LinkStatus, RadarMemory},
+    drawing::{draw, load_default_font},
+};
+
+pub static USAGE: &str =
+    "Usage: dcs-hemmecs snapshot [--size WIDTHxHEIGHT] [--data FILE.json] [--config FILE.toml] OUTPUT.png";
+
+/// Renders a single HMD frame, without needing any windows
+///
+/// If no flight data is given, the same sample data as the "Show sample data" option is drawn.
+fn render(
+    config: &Config,
+    data: Option<&FlightData>,
+    screen_dimensions: (i32, i32),
+    font: &Font,
+) -> DrawTarget {
+    let mut config = config.clone();
+    config.show_sample_data = data.is_none();
+
+    let mut draw_target = DrawTarget::new(screen_dimensions.0, screen_dimensions.1);
+    draw(
+        &config,
+        &data.cloned(),
+        LinkStatus::Connected,
+        &mut RadarMemory::default(),
+        &mut draw_target,
+        screen_dimensions,
+        font,
+    );
+    draw_target
+}
+
+/// Renders a single HMD frame into a PNG file, as described in `render`
+pub fn render_png(
+    config: &Config,
+    data: Option<&FlightData>,
+    screen_dimensions: (i32, i32),
+    font: &Font,
+    path: &Path,
+) -> Result<()> {
+    render(config, data, screen_dimensions, font)
+        .write_png(path)
+        .map_err(|err| anyhow!("failed to write {:?}: {:?}", path, err))
+}
+
+pub fn read_file(path: &str) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut buf))
+        .context(format!("cannot read {:?}", path))?;
+    Ok(buf)
+}
+
+fn parse_size(size: &str) -> Option<(i32, i32)> {
+    let mut parts = size.split('x');
+    let width = parts.next()?.parse().ok()?;
+    let height = parts.next()?.parse().ok()?;
+    match parts.next() {
+        None if width > 0 && height > 0 => Some((width, height)),
+        _ => None,
+    }
+}
+
+/// Entry point of the `snapshot` subcommand
+pub fn run(args: &[String]) -> Result<()> {
+    let mut screen_dimensions = (1920, 1080);
+    let mut data = None;
+    let mut config = Config::default();
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--size" => {
+                let size = args.next().ok_or_else(|| anyhow!(USAGE))?;
+                screen_dimensions = parse_size(size)
+                    .ok_or_else(|| anyhow!("invalid size {:?}, expected e.g. 1920x1080", size))?;
+            }
+            "--data" => {
+                let path = args.next().ok_or_else(|| anyhow!(USAGE))?;
+                let flight_data: FlightData = serde_json::from_slice(&read_file(path)?)
+                    .context(format!("invalid flight data in {:?}", path))?;
+                data = Some(flight_data);
+            }
+            "--config" => {
+                let path = args.next().ok_or_else(|| anyhow!(USAGE))?;
+                config = toml::from_slice(&read_file(path)?)
+                    .context(format!("invalid config in {:?}", path))?;
+            }
+            _ if output.is_none() && !arg.starts_with("--") => output = Some(PathBuf::from(arg)),
+            _ => bail!(USAGE),
+        }
+    }
+
+    let output = output.ok_or_else(|| anyhow!(USAGE))?;
+    render_png(
+        &config,
+        data.as_ref(),
+        screen_dimensions,
+        &load_default_font(),
+        &output,
+    )?;
+    println!("Saved snapshot to {:?}", output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_sample_data() {
+        // Without flight data the renderer falls back to FlightData::sample()
+        let frame = render(&Config::default(), None, (640, 360), &load_default_font());
+        assert_eq!((frame.width(), frame.height()), (640, 360));
+
+        // Anything but a fully transparent frame means the HMD drew something
+        let blank = DrawTarget::new(640, 360);
+        assert!(frame.get_data() != blank.get_data());
+    }
+}
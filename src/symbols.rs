@@ -1,4 +1,4 @@
-use raqote::{Color, DrawTarget, PathBuilder, StrokeStyle};
+use raqote::{Color, DrawTarget, PathBuilder, Source, StrokeStyle};
 use std::f32::consts::PI;
 
 use crate::consts::ANTI_ALIASED;
@@ -10,7 +10,7 @@ pub enum Identification {
     Unknown,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Donor {
     Ownship,
     Datalink,
@@ -18,6 +18,12 @@ pub enum Donor {
 
 const SYMBOL_RADIUS: f32 = 16.0;
 const STROKE_WIDTH: f32 = 2.0;
+const STALE_ALPHA: u8 = 96;
+const RETICLE_RADIUS: f32 = 24.0;
+const RETICLE_GAP: f32 = 6.0;
+const FLIGHT_PATH_MARKER_RADIUS: f32 = 10.0;
+pub const ARROW_LENGTH: f32 = 32.0;
+const ARROW_WIDTH: f32 = 24.0;
 
 pub fn draw_symbol(
     draw_target: &mut DrawTarget,
@@ -26,6 +32,7 @@ pub fn draw_symbol(
     iff: &Identification,
     src: &Donor,
     locked: bool,
+    stale: bool,
 ) {
     let shape = match iff {
         Identification::Hostile => {
@@ -67,10 +74,12 @@ pub fn draw_symbol(
         }
     };
 
+    // Dim targets which are not being actively tracked anymore
+    let alpha = if stale { STALE_ALPHA } else { 255 };
     let color = match iff {
-        Identification::Hostile => Color::new(255, 192, 0, 0).into(),
-        Identification::Friendly => Color::new(255, 0, 0, 192).into(),
-        Identification::Unknown => Color::new(255, 192, 192, 0).into(),
+        Identification::Hostile => Color::new(alpha, 192, 0, 0).into(),
+        Identification::Friendly => Color::new(alpha, 0, 0, 192).into(),
+        Identification::Unknown => Color::new(alpha, 192, 192, 0).into(),
     };
 
     let stroke = match src {
@@ -93,6 +102,79 @@ pub fn draw_symbol(
     draw_target.stroke(&shape, &color, &stroke, &ANTI_ALIASED);
 }
 
+/// Draws a circle with a cross in the middle, which is left open so whatever it is lined up
+/// with stays visible
+pub fn draw_reticle(draw_target: &mut DrawTarget, x: f32, y: f32, color: &Source) {
+    let mut pb = PathBuilder::new();
+    pb.arc(x, y, RETICLE_RADIUS, 0.0, PI * 2.0);
+    for &(dx, dy) in [(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)].iter() {
+        pb.move_to(x + dx * RETICLE_GAP, y + dy * RETICLE_GAP);
+        pb.line_to(x + dx * RETICLE_RADIUS * 2.0, y + dy * RETICLE_RADIUS * 2.0);
+    }
+    let stroke = StrokeStyle {
+        width: STROKE_WIDTH,
+        ..Default::default()
+    };
+    draw_target.stroke(&pb.finish(), color, &stroke, &ANTI_ALIASED);
+}
+
+/// Draws the flight path marker, crossed out when it is held at the edge of the screen instead
+/// of where the aircraft is actually going
+pub fn draw_flight_path_marker(
+    draw_target: &mut DrawTarget,
+    x: f32,
+    y: f32,
+    clamped: bool,
+    color: &Source,
+) {
+    let r = FLIGHT_PATH_MARKER_RADIUS;
+    let mut pb = PathBuilder::new();
+    pb.arc(x, y, r, 0.0, PI * 2.0);
+    // Wings
+    pb.move_to(x - r, y);
+    pb.line_to(x - r * 2.5, y);
+    pb.move_to(x + r, y);
+    pb.line_to(x + r * 2.5, y);
+    // Tail
+    pb.move_to(x, y - r);
+    pb.line_to(x, y - r * 2.0);
+    if clamped {
+        pb.move_to(x - r * 1.5, y - r * 1.5);
+        pb.line_to(x + r * 1.5, y + r * 1.5);
+        pb.move_to(x + r * 1.5, y - r * 1.5);
+        pb.line_to(x - r * 1.5, y + r * 1.5);
+    }
+    let stroke = StrokeStyle {
+        width: STROKE_WIDTH,
+        ..Default::default()
+    };
+    draw_target.stroke(&pb.finish(), color, &stroke, &ANTI_ALIASED);
+}
+
+/// Draws an arrow with its tip at the given point, pointing in the direction of a unit vector
+pub fn draw_locator_arrow(
+    draw_target: &mut DrawTarget,
+    x: f32,
+    y: f32,
+    direction: (f32, f32),
+    color: &Source,
+) {
+    let (dx, dy) = direction;
+    let base = (x - dx * ARROW_LENGTH, y - dy * ARROW_LENGTH);
+    let mut pb = PathBuilder::new();
+    pb.move_to(x, y);
+    pb.line_to(
+        base.0 - dy * ARROW_WIDTH / 2.0,
+        base.1 + dx * ARROW_WIDTH / 2.0,
+    );
+    pb.line_to(
+        base.0 + dy * ARROW_WIDTH / 2.0,
+        base.1 - dx * ARROW_WIDTH / 2.0,
+    );
+    pb.close();
+    draw_target.fill(&pb.finish(), color, &ANTI_ALIASED);
+}
+
 #[allow(dead_code)]
 pub fn display_gallery(draw_target: &mut DrawTarget, x: f32, y: f32) {
     for (i, iff) in [
@@ -112,6 +194,7 @@ pub fn display_gallery(draw_target: &mut DrawTarget, x: f32, y: f32) {
                     iff,
                     src,
                     locked,
+                    false,
                 );
             }
         }
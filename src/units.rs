@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{UnitMode, Units},
+    data::{FlightData, UnitSystem},
+};
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum SpeedUnit {
+    #[serde(rename = "kt")]
+    Knots,
+    #[serde(rename = "km/h")]
+    KilometersPerHour,
+    #[serde(rename = "mph")]
+    MilesPerHour,
+    #[serde(rename = "m/s")]
+    MetersPerSecond,
+}
+
+impl SpeedUnit {
+    /// Converts from DCS units (m/s)
+    pub fn convert(self, mps: f32) -> f32 {
+        match self {
+            SpeedUnit::Knots => mps * 1.943844,
+            SpeedUnit::KilometersPerHour => mps * 3.6,
+            SpeedUnit::MilesPerHour => mps * 2.236936,
+            SpeedUnit::MetersPerSecond => mps,
+        }
+    }
+
+    /// Number of digits needed to display any reasonable airspeed
+    pub fn digits(self) -> usize {
+        match self {
+            SpeedUnit::KilometersPerHour => 4,
+            _ => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum AltitudeUnit {
+    #[serde(rename = "ft")]
+    Feet,
+    #[serde(rename = "m")]
+    Meters,
+}
+
+impl AltitudeUnit {
+    /// Converts from DCS units (m)
+    pub fn convert(self, meters: f32) -> f32 {
+        match self {
+            AltitudeUnit::Feet => meters * 3.28084,
+            AltitudeUnit::Meters => meters,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum FuelUnit {
+    #[serde(rename = "lb")]
+    Pounds,
+    #[serde(rename = "kg")]
+    Kilograms,
+}
+
+impl FuelUnit {
+    /// Converts from DCS units (kg)
+    pub fn convert(self, kg: f32) -> f32 {
+        match self {
+            FuelUnit::Pounds => kg * 2.2046225,
+            FuelUnit::Kilograms => kg,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            FuelUnit::Pounds => "lbs",
+            FuelUnit::Kilograms => "kg",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum DistanceUnit {
+    #[serde(rename = "nm")]
+    NauticalMiles,
+    #[serde(rename = "km")]
+    Kilometers,
+    #[serde(rename = "mi")]
+    Miles,
+}
+
+impl DistanceUnit {
+    /// Converts from DCS units (m)
+    pub fn convert(self, meters: f32) -> f32 {
+        match self {
+            DistanceUnit::NauticalMiles => meters / 1852.0,
+            DistanceUnit::Kilometers => meters / 1000.0,
+            DistanceUnit::Miles => meters / 1609.344,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            DistanceUnit::NauticalMiles => "nm",
+            DistanceUnit::Kilometers => "km",
+            DistanceUnit::Miles => "mi",
+        }
+    }
+}
+
+/// The units every value on the HMD is converted to before being displayed
+#[derive(Clone, Copy)]
+pub struct DisplayUnits {
+    pub speed: SpeedUnit,
+    pub altitude: AltitudeUnit,
+    pub fuel: FuelUnit,
+    pub distance: DistanceUnit,
+}
+
+impl From<UnitSystem> for DisplayUnits {
+    fn from(unit_system: UnitSystem) -> Self {
+        match unit_system {
+            UnitSystem::Metric => DisplayUnits {
+                speed: SpeedUnit::KilometersPerHour,
+                altitude: AltitudeUnit::Meters,
+                fuel: FuelUnit::Kilograms,
+                distance: DistanceUnit::Kilometers,
+            },
+            UnitSystem::Imperial => DisplayUnits {
+                speed: SpeedUnit::Knots,
+                altitude: AltitudeUnit::Feet,
+                fuel: FuelUnit::Pounds,
+                distance: DistanceUnit::NauticalMiles,
+            },
+        }
+    }
+}
+
+/// Picks the units for the current aircraft, applying any per-airframe overrides on top of the
+/// selected unit mode
+pub fn display_units(config: &Units, data: &FlightData) -> DisplayUnits {
+    let base = match config.mode {
+        UnitMode::FollowAircraft => DisplayUnits::from(data.get_unit_system()),
+        UnitMode::Custom => DisplayUnits {
+            speed: config.speed,
+            altitude: config.altitude,
+            fuel: config.fuel,
+            distance: config.distance,
+        },
+    };
+    match config.aircraft.get(&data.unit) {
+        Some(overrides) => DisplayUnits {
+            speed: overrides.speed.unwrap_or(base.speed),
+            altitude: overrides.altitude.unwrap_or(base.altitude),
+            fuel: overrides.fuel.unwrap_or(base.fuel),
+            distance: overrides.distance.unwrap_or(base.distance),
+        },
+        None => base,
+    }
+}
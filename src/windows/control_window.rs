@@ -1,15 +1,20 @@
 use anyhow::{anyhow, Context, Result};
 use nwd::NwgUi;
 use nwg::{
-    Button, CheckBox, CheckBoxState, ColorDialog, Font, GridLayout, Label, NativeUi, TrackBar,
-    Window,
+    Button, CheckBox, CheckBoxState, ColorDialog, Font, GridLayout, Label, NativeUi, Timer,
+    TrackBar, Window,
 };
 use once_cell::sync::Lazy;
 use std::cell::{Cell, RefCell};
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{Arc, RwLock};
 use winapi::shared::windef::HWND;
 
-use crate::config::{self, Config, ConfigHandle};
+use crate::config::{self, Config, ConfigHandle, Connection, Projection};
+use crate::data::{FlightData, LinkStatus};
 use crate::installer::{self, DCSVersion, InstallStatus};
+use crate::recording::ReplayControl;
+use crate::windows::get_screen_dimensions;
 
 static HEADING_FONT: Lazy<Font> = Lazy::new(|| {
     let mut font = Default::default();
@@ -22,15 +27,25 @@ static HEADING_FONT: Lazy<Font> = Lazy::new(|| {
     font
 });
 
+/// How many seconds the replay seek buttons move the playback position by
+const REPLAY_SEEK_STEP: f32 = 10.0;
+
+/// How much each click of the calibration buttons changes the projection by
+const FOV_STEP: f32 = 0.5;
+const OFFSET_STEP: f32 = 2.0;
+const SCALE_STEP: f32 = 0.005;
+
 #[derive(Default, NwgUi)]
 pub struct ControlWindow {
     config: RefCell<Option<ConfigHandle>>,
+    replay: RefCell<Option<Arc<ReplayControl>>>,
+    link_status: RefCell<Option<Arc<RwLock<LinkStatus>>>>,
 
-    #[nwg_control(size: (320, 400), title: "DCS Hemmecs", flags: "WINDOW|VISIBLE")]
+    #[nwg_control(size: (320, 530), title: "DCS Hemmecs", flags: "WINDOW|VISIBLE")]
     #[nwg_events(OnWindowClose: [ControlWindow::on_close])]
     pub window: Window,
 
-    #[nwg_layout(parent: window, max_row: Some(12), max_column: Some(8))]
+    #[nwg_layout(parent: window, max_row: Some(16), max_column: Some(8))]
     grid: GridLayout,
 
     #[nwg_control(font: Some(&HEADING_FONT), text: "Installer")]
@@ -108,12 +123,127 @@ pub struct ControlWindow {
     #[nwg_layout_item(layout: grid, row: 7, col: 3, col_span: 5)]
     hide_in_cockpit_checkbox: CheckBox,
 
+    #[nwg_control(text: "Replay", flags: "NONE")]
+    #[nwg_layout_item(layout: grid, row: 9, col_span: 3)]
+    replay_label: Label,
+
+    #[nwg_control(text: "Pause", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_replay_pause]
+    )]
+    #[nwg_layout_item(layout: grid, row: 9, col: 3, col_span: 3)]
+    replay_pause_button: Button,
+
+    #[nwg_control(text: "<<", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_replay_rewind]
+    )]
+    #[nwg_layout_item(layout: grid, row: 9, col: 6)]
+    replay_rewind_button: Button,
+
+    #[nwg_control(text: ">>", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_replay_forward]
+    )]
+    #[nwg_layout_item(layout: grid, row: 9, col: 7)]
+    replay_forward_button: Button,
+
+    #[nwg_control(text: "")]
+    #[nwg_layout_item(layout: grid, row: 10, col_span: 8)]
+    link_status_label: Label,
+
+    #[nwg_control(parent: window, interval: 500, stopped: false)]
+    #[nwg_events(OnTimerTick: [ControlWindow::update_link_status])]
+    link_status_timer: Timer,
+
     #[nwg_control(text: "Show sample data")]
     #[nwg_events(
         OnButtonClick: [ControlWindow::save_config]
     )]
     #[nwg_layout_item(layout: grid, row: 11, col_span: 8)]
     sample_checkbox: CheckBox,
+
+    #[nwg_control(text: "Calibrate projection")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_calibrate]
+    )]
+    #[nwg_layout_item(layout: grid, row: 12, col_span: 5)]
+    calibrate_checkbox: CheckBox,
+
+    #[nwg_control(text: "Reset", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_calibration_reset]
+    )]
+    #[nwg_layout_item(layout: grid, row: 12, col: 5, col_span: 3)]
+    calibration_reset_button: Button,
+
+    #[nwg_control(text: "Field of view", flags: "NONE")]
+    #[nwg_layout_item(layout: grid, row: 13, col_span: 4)]
+    fov_label: Label,
+
+    #[nwg_control(text: "-", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_fov_decrease]
+    )]
+    #[nwg_layout_item(layout: grid, row: 13, col: 4, col_span: 2)]
+    fov_decrease_button: Button,
+
+    #[nwg_control(text: "+", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_fov_increase]
+    )]
+    #[nwg_layout_item(layout: grid, row: 13, col: 6, col_span: 2)]
+    fov_increase_button: Button,
+
+    #[nwg_control(text: "Offset", flags: "NONE")]
+    #[nwg_layout_item(layout: grid, row: 14, col_span: 4)]
+    offset_label: Label,
+
+    #[nwg_control(text: "←", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_offset_left]
+    )]
+    #[nwg_layout_item(layout: grid, row: 14, col: 4)]
+    offset_left_button: Button,
+
+    #[nwg_control(text: "→", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_offset_right]
+    )]
+    #[nwg_layout_item(layout: grid, row: 14, col: 5)]
+    offset_right_button: Button,
+
+    #[nwg_control(text: "↑", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_offset_up]
+    )]
+    #[nwg_layout_item(layout: grid, row: 14, col: 6)]
+    offset_up_button: Button,
+
+    #[nwg_control(text: "↓", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_offset_down]
+    )]
+    #[nwg_layout_item(layout: grid, row: 14, col: 7)]
+    offset_down_button: Button,
+
+    #[nwg_control(text: "Scale", flags: "NONE")]
+    #[nwg_layout_item(layout: grid, row: 15, col_span: 4)]
+    scale_label: Label,
+
+    #[nwg_control(text: "-", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_scale_decrease]
+    )]
+    #[nwg_layout_item(layout: grid, row: 15, col: 4, col_span: 2)]
+    scale_decrease_button: Button,
+
+    #[nwg_control(text: "+", flags: "NONE")]
+    #[nwg_events(
+        OnButtonClick: [ControlWindow::on_scale_increase]
+    )]
+    #[nwg_layout_item(layout: grid, row: 15, col: 6, col_span: 2)]
+    scale_increase_button: Button,
 }
 
 impl ControlWindow {
@@ -176,6 +306,89 @@ impl ControlWindow {
         }
     }
 
+    /// Shows the calibration controls while the calibration reticle is drawn
+    fn on_calibrate(&self) {
+        let calibrating = self.calibrate_checkbox.check_state() == CheckBoxState::Checked;
+        self.calibration_reset_button.set_visible(calibrating);
+        self.fov_label.set_visible(calibrating);
+        self.fov_decrease_button.set_visible(calibrating);
+        self.fov_increase_button.set_visible(calibrating);
+        self.offset_label.set_visible(calibrating);
+        self.offset_left_button.set_visible(calibrating);
+        self.offset_right_button.set_visible(calibrating);
+        self.offset_up_button.set_visible(calibrating);
+        self.offset_down_button.set_visible(calibrating);
+        self.scale_label.set_visible(calibrating);
+        self.scale_decrease_button.set_visible(calibrating);
+        self.scale_increase_button.set_visible(calibrating);
+        if let Some(config) = &*self.config.borrow() {
+            config.lock().unwrap().calibrating = calibrating;
+        }
+    }
+
+    /// Changes the projection settings and saves them right away, so the HMD follows along
+    fn adjust_projection(&self, adjust: impl FnOnce(&mut Projection)) {
+        if let Some(config) = &*self.config.borrow() {
+            let config = &mut config.lock().unwrap();
+            adjust(&mut config.projection);
+            config::save_config(&config)
+                .map_err(|err| eprintln!("Failed to save config changes: {:?}", err))
+                .ok();
+        }
+    }
+
+    /// Horizontal field of view the HMD is currently drawn with, in degrees
+    fn current_fov(&self, projection: &Projection) -> f32 {
+        let (width, height) = get_screen_dimensions();
+        FlightData::horizontal_fov(projection, width as f32 / height as f32)
+    }
+
+    fn nudge_fov(&self, step: f32) {
+        self.adjust_projection(|projection| {
+            // Start from the default, until the user has set their own
+            let fov = self.current_fov(projection) + step;
+            projection.fov = Some(fov.max(FOV_STEP));
+        });
+    }
+
+    fn on_fov_decrease(&self) {
+        self.nudge_fov(-FOV_STEP);
+    }
+
+    fn on_fov_increase(&self) {
+        self.nudge_fov(FOV_STEP);
+    }
+
+    fn on_offset_left(&self) {
+        self.adjust_projection(|projection| projection.offset.0 -= OFFSET_STEP);
+    }
+
+    fn on_offset_right(&self) {
+        self.adjust_projection(|projection| projection.offset.0 += OFFSET_STEP);
+    }
+
+    fn on_offset_up(&self) {
+        self.adjust_projection(|projection| projection.offset.1 -= OFFSET_STEP);
+    }
+
+    fn on_offset_down(&self) {
+        self.adjust_projection(|projection| projection.offset.1 += OFFSET_STEP);
+    }
+
+    fn on_scale_decrease(&self) {
+        self.adjust_projection(|projection| {
+            projection.scale = (projection.scale - SCALE_STEP).max(SCALE_STEP)
+        });
+    }
+
+    fn on_scale_increase(&self) {
+        self.adjust_projection(|projection| projection.scale += SCALE_STEP);
+    }
+
+    fn on_calibration_reset(&self) {
+        self.adjust_projection(|projection| *projection = Projection::default());
+    }
+
     fn set_installer_state(&self, install_button: &Button, status: &InstallStatus) {
         match status {
             InstallStatus::DCSNotFound => {
@@ -197,12 +410,21 @@ impl ControlWindow {
         }
     }
 
+    /// Connection settings the export script should be installed with
+    fn connection(&self) -> Connection {
+        match &*self.config.borrow() {
+            Some(config) => config.lock().unwrap().connection.clone(),
+            None => Connection::default(),
+        }
+    }
+
     fn run_installer(&self, dcs_version: &DCSVersion) -> Result<()> {
-        match dcs_version.install_status()? {
-            InstallStatus::NotInstalled => installer::install(&dcs_version),
+        let connection = self.connection();
+        match dcs_version.install_status(&connection)? {
+            InstallStatus::NotInstalled => installer::install(&dcs_version, &connection),
             InstallStatus::RequiresUpdate => {
                 installer::uninstall(&dcs_version)?;
-                installer::install(&dcs_version)
+                installer::install(&dcs_version, &connection)
             }
             InstallStatus::Installed => installer::uninstall(&dcs_version),
             InstallStatus::DCSNotFound => Err(anyhow!("Cannot install in non existing DCS folder")),
@@ -220,10 +442,11 @@ impl ControlWindow {
     }
 
     pub fn update_install_status(&self) {
-        if let Ok(status) = DCSVersion::Stable.install_status() {
+        let connection = self.connection();
+        if let Ok(status) = DCSVersion::Stable.install_status(&connection) {
             self.set_installer_state(&self.install_stable_button, &status);
         }
-        if let Ok(status) = DCSVersion::Openbeta.install_status() {
+        if let Ok(status) = DCSVersion::Openbeta.install_status(&connection) {
             self.set_installer_state(&self.install_openbeta_button, &status);
         }
     }
@@ -235,6 +458,54 @@ impl ControlWindow {
         *self.config.borrow_mut() = config;
     }
 
+    fn on_replay_pause(&self) {
+        if let Some(replay) = &*self.replay.borrow() {
+            let paused = !replay.paused.load(Relaxed);
+            replay.paused.store(paused, Relaxed);
+            self.replay_pause_button
+                .set_text(if paused { "Resume" } else { "Pause" });
+        }
+    }
+
+    fn on_replay_rewind(&self) {
+        if let Some(replay) = &*self.replay.borrow() {
+            replay.seek_by(-REPLAY_SEEK_STEP);
+        }
+    }
+
+    fn on_replay_forward(&self) {
+        if let Some(replay) = &*self.replay.borrow() {
+            replay.seek_by(REPLAY_SEEK_STEP);
+        }
+    }
+
+    fn update_link_status(&self) {
+        if let Some(link_status) = &*self.link_status.borrow() {
+            let text = match *link_status.read().unwrap() {
+                LinkStatus::Disconnected => "Waiting for mission start",
+                LinkStatus::Connected => "Connected to DCS",
+                LinkStatus::ProtocolMismatch => "Protocol mismatch, check HemmecsExport.lua",
+                LinkStatus::ExporterOutdated => "Exporter outdated, click Update",
+            };
+            self.link_status_label.set_text(text);
+        }
+    }
+
+    pub fn set_link_status(&self, link_status: Option<Arc<RwLock<LinkStatus>>>) {
+        *self.link_status.borrow_mut() = link_status;
+        self.update_link_status();
+    }
+
+    /// Shows the playback controls, which are hidden unless a recording is being replayed
+    pub fn set_replay(&self, replay: Option<Arc<ReplayControl>>) {
+        let visible = replay.is_some();
+        self.replay_label.set_visible(visible);
+        self.replay_pause_button.set_visible(visible);
+        self.replay_rewind_button.set_visible(visible);
+        self.replay_forward_button.set_visible(visible);
+        *self.replay.borrow_mut() = replay;
+    }
+
     pub fn hwnd(&self) -> HWND {
         self.window.handle.hwnd().unwrap()
     }
@@ -52,7 +52,8 @@ unsafe extern "system" fn window_proc(hwnd: HWND, msg: u32, wparam: usize, lpara
 
                 // Unpack the data fields
                 let mut draw_target = state.draw_target.borrow_mut();
-                let flight_data = { state.flight_data.read().unwrap().clone() };
+                let flight_data = { state.flight_data.read().unwrap().interpolated() };
+                let link_status = { *state.link_status.read().unwrap() };
                 let config = { state.config.lock().unwrap().clone() };
                 let (width, height) = state.screen_dimensions;
                 let font = state.font.borrow();
@@ -82,6 +83,7 @@ unsafe extern "system" fn window_proc(hwnd: HWND, msg: u32, wparam: usize, lpara
                 let pixels = draw(
                     &config,
                     &flight_data,
+                    link_status,
                     &mut state.radar_memory.write().unwrap(),
                     &mut draw_target,
                     state.screen_dimensions,
@@ -4,6 +4,7 @@ pub mod hmd_window;
 use std::ffi::CString;
 use std::ptr::null_mut as NULL;
 use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
 use winapi::um::winuser::{MessageBoxA, IDOK, MB_ICONERROR, MB_ICONINFORMATION};
 use winapi::{
     shared::windef::HWND,
@@ -50,3 +51,11 @@ pub fn show_message_box(msg_type: MessageBoxType) -> bool {
 pub fn get_screen_dimensions() -> (i32, i32) {
     unsafe { (GetSystemMetrics(SM_CXSCREEN), GetSystemMetrics(SM_CYSCREEN)) }
 }
+
+/// Connects the standard output and error streams to the console of the parent process, if it has
+/// one, which does nothing when started from Explorer
+pub fn attach_console() {
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
@@ -1,30 +1,294 @@
-use std::io::{self, BufRead, BufReader, ErrorKind};
-use std::net::TcpStream;
-use std::panic::{catch_unwind, resume_unwind};
+use serde::Deserialize;
+use std::fmt::Display;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
 use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
 use std::sync::RwLock;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::data::{FlightData, RadarMemory};
+use crate::binary::{self, read_message, Decoder};
+use crate::config::{ConfigHandle, Connection, Encoding, Transport};
+use crate::data::{dcs, FlightData, FlightDataSamples, LinkStatus, RadarMemory};
+use crate::installer;
+use crate::recording::Recorder;
+use crate::relay::RelayServer;
+
+/// How many lines in a row can fail to parse before giving up on the connection's data
+const MAX_PARSE_ERRORS: u32 = 10;
+/// How much of an invalid line is logged
+const ERROR_SNIPPET_LENGTH: usize = 120;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long a remote computer can go without sending anything before the connection is dropped
+const REMOTE_READ_TIMEOUT: Duration = Duration::from_secs(10);
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
+/// How often the UDP subscription is renewed, which must be well below `SUBSCRIPTION_TIMEOUT` in
+/// the export script
+const SUBSCRIBE_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the export script can go without sending any datagrams before it is considered gone
+const UDP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest possible UDP datagram
+const MAX_DATAGRAM_SIZE: usize = 65536;
+/// How far back a sequence number can jump before it means the export script was restarted,
+/// rather than a frame arriving late
+const MAX_REORDERING: u32 = 120;
+/// How often lost and reordered frames are reported
+const SEQUENCE_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Anything the export script sends, told apart by its fields
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Message {
+    Hello(dcs::Hello),
+    // Boxed, as it is much larger than the greeting
+    FlightData(Box<FlightData>),
+}
+
+/// Checks whether the export script which sent the greeting is the one bundled with the application
+fn is_exporter_outdated(hello: &dcs::Hello, connection: &Connection) -> bool {
+    let info = &hello.hello;
+    println!(
+        "Exporter protocol version {}, script hash {:08x}",
+        info.protocol, info.script_hash
+    );
+    // The script on another computer is generated from that computer's config, so its hash can't
+    // be checked here
+    info.protocol != installer::PROTOCOL_VERSION
+        || (connection.remote_host.is_none()
+            && info.script_hash != installer::script_hash(connection))
+}
+
+/// Saves a line to the recording, if there is one, giving up on it rather than on the connection if
+/// it can't be written
+fn record_line(recorder: &mut Option<Recorder>, line: &str) {
+    if let Some(file) = recorder {
+        if let Err(err) = file.record(line) {
+            eprintln!("Warning: stopped recording telemetry: {}", err);
+            *recorder = None;
+        }
+    }
+}
+
+/// Parses the frames received from the export script and publishes them to the rest of the
+/// application, regardless of how they were transported
+struct FrameHandler<'a> {
+    connection: &'a Connection,
+    data_handle: &'a RwLock<FlightDataSamples>,
+    link_handle: &'a RwLock<LinkStatus>,
+    recorder: &'a mut Option<Recorder>,
+    relay: &'a RelayServer,
+    decoder: Decoder,
+    /// Last greeting received, which is repeated over UDP in case it gets lost
+    hello: Option<dcs::ExporterInfo>,
+    /// Whether the first message had to be a greeting, which is only guaranteed over TCP
+    require_hello: bool,
+    outdated: bool,
+    parse_errors: u32,
+    consecutive_errors: u32,
+    last_seq: Option<u32>,
+    lost_frames: u32,
+    late_frames: u32,
+    last_report: Instant,
+}
+
+impl<'a> FrameHandler<'a> {
+    fn new(
+        connection: &'a Connection,
+        data_handle: &'a RwLock<FlightDataSamples>,
+        link_handle: &'a RwLock<LinkStatus>,
+        recorder: &'a mut Option<Recorder>,
+        relay: &'a RelayServer,
+    ) -> Self {
+        FrameHandler {
+            connection,
+            data_handle,
+            link_handle,
+            recorder,
+            relay,
+            decoder: Decoder::default(),
+            hello: None,
+            require_hello: connection.transport == Transport::Tcp,
+            outdated: false,
+            parse_errors: 0,
+            consecutive_errors: 0,
+            last_seq: None,
+            lost_frames: 0,
+            late_frames: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    fn set_outdated(&mut self, outdated: bool) {
+        if outdated && !self.outdated {
+            println!("Warning: the installed HemmecsExport.lua is outdated");
+        }
+        self.outdated = outdated;
+        self.require_hello = false;
+    }
+
+    fn handle(&mut self, line: &str) -> io::Result<()> {
+        record_line(self.recorder, line);
+
+        let flight_data = match serde_json::from_str(line) {
+            Ok(Message::Hello(hello)) => {
+                if self.hello.as_ref() != Some(&hello.hello) {
+                    self.set_outdated(is_exporter_outdated(&hello, self.connection));
+                    self.hello = Some(hello.hello);
+                }
+                return Ok(());
+            }
+            Ok(Message::FlightData(flight_data)) => Ok(*flight_data),
+            // The untagged enum can't tell what was wrong, but the flight data on its own can
+            Err(err) => Err(serde_json::from_str::<FlightData>(line)
+                .err()
+                .unwrap_or(err)),
+        };
+        // Scripts from before the handshake was introduced start sending flight data right away
+        if self.require_hello {
+            self.set_outdated(true);
+        }
+
+        match flight_data {
+            Ok(flight_data) if self.check_sequence(flight_data.seq) => self.publish(flight_data),
+            Ok(_) => (),
+            Err(err) => self.skip_invalid(&err, line.chars().take(ERROR_SNIPPET_LENGTH).collect()),
+        }
+        Ok(())
+    }
+
+    /// Handles a message in the binary encoding, where the greeting is still sent as JSON
+    fn handle_binary(&mut self, payload: &[u8]) -> io::Result<()> {
+        if payload.first() == Some(&b'{') {
+            return self.handle(&String::from_utf8_lossy(payload));
+        }
+        if self.require_hello {
+            self.set_outdated(true);
+        }
+        // Late frames are dropped before decoding, so they can't replace the sections remembered
+        // from newer ones
+        if !self.check_sequence(binary::sequence(payload)) {
+            return Ok(());
+        }
+
+        match self.decoder.decode(payload) {
+            Ok(flight_data) => {
+                // Recordings are always saved as JSON, which can be replayed on its own
+                if self.recorder.is_some() {
+                    record_line(self.recorder, &serde_json::to_string(&flight_data)?);
+                }
+                self.publish(flight_data);
+            }
+            Err(err) => self.skip_invalid(&err, format!("({} bytes)", payload.len())),
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self, flight_data: FlightData) {
+        self.relay.broadcast(&flight_data);
+        self.data_handle.write().unwrap().push(flight_data);
+        *self.link_handle.write().unwrap() = if self.outdated {
+            LinkStatus::ExporterOutdated
+        } else {
+            LinkStatus::Connected
+        };
+        self.consecutive_errors = 0;
+    }
+
+    /// Keeps the last valid data around, in case it was just a transmission error
+    fn skip_invalid(&mut self, err: &dyn Display, snippet: String) {
+        self.parse_errors += 1;
+        self.consecutive_errors += 1;
+        eprintln!(
+            "Warning: skipping invalid data from DCS ({} errors so far): {}\n  {}",
+            self.parse_errors, err, snippet
+        );
+        if self.consecutive_errors >= MAX_PARSE_ERRORS {
+            *self.link_handle.write().unwrap() = LinkStatus::ProtocolMismatch;
+        }
+    }
+
+    /// Keeps count of lost frames, returning whether the frame is newer than the last one shown
+    fn check_sequence(&mut self, seq: Option<u32>) -> bool {
+        let seq = match seq {
+            Some(seq) => seq,
+            None => return true,
+        };
+        let in_order = match self.last_seq {
+            Some(last) if seq > last => {
+                self.lost_frames += seq - last - 1;
+                true
+            }
+            // Showing an older frame would make the symbols jump back
+            Some(last) if last - seq <= MAX_REORDERING => {
+                self.late_frames += 1;
+                false
+            }
+            _ => true,
+        };
+        if in_order {
+            self.last_seq = Some(seq);
+        }
+
+        if self.last_report.elapsed() >= SEQUENCE_REPORT_INTERVAL {
+            if self.lost_frames > 0 || self.late_frames > 0 {
+                println!(
+                    "Warning: {} frames from DCS were lost and {} arrived out of order in the last {} seconds",
+                    self.lost_frames,
+                    self.late_frames,
+                    SEQUENCE_REPORT_INTERVAL.as_secs()
+                );
+            }
+            self.lost_frames = 0;
+            self.late_frames = 0;
+            self.last_report = Instant::now();
+        }
+        in_order
+    }
+}
+
+fn resolve(connection: &Connection) -> io::Result<SocketAddr> {
+    (connection.server_host(), connection.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "could not resolve DCS host"))
+}
+
+/// Opens the connection to the export script, sending the shared secret first if there is one
+fn connect(connection: &Connection) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect_timeout(&resolve(connection)?, CONNECT_TIMEOUT)?;
+    if !connection.secret.is_empty() {
+        writeln!(stream, "{}", connection.secret)?;
+    }
+    if connection.remote_host.is_some() {
+        // Notice when the other computer goes away, even if the connection was not closed
+        stream.set_read_timeout(Some(REMOTE_READ_TIMEOUT))?;
+    }
+    Ok(stream)
+}
 
 fn handle_data_connection(
     stream: TcpStream,
-    data_handle: &RwLock<Option<FlightData>>,
-    radar_handle: &RwLock<RadarMemory>,
+    handler: &mut FrameHandler,
     quit_signal: &AtomicBool,
 ) -> io::Result<()> {
     stream.set_nodelay(true)?;
-    let mut lines = BufReader::new(stream).lines();
-    while quit_signal.load(Relaxed) == false {
-        let line = lines.next();
-        let mut data = data_handle.write().unwrap();
-        if let Some(line) = line {
-            *data = Some(serde_json::from_str(&line?).unwrap());
-        } else {
-            radar_handle.write().unwrap().targets.clear();
-            *data = None;
-            break;
+    let mut reader = BufReader::new(stream);
+    match handler.connection.encoding {
+        Encoding::Json => {
+            for line in reader.lines() {
+                if quit_signal.load(Relaxed) {
+                    break;
+                }
+                handler.handle(&line?)?;
+            }
+        }
+        Encoding::Binary => {
+            let mut buf = Vec::new();
+            while quit_signal.load(Relaxed) == false && read_message(&mut reader, &mut buf)? {
+                handler.handle_binary(&buf)?;
+            }
         }
     }
     // Connection closed normally
@@ -32,44 +296,128 @@ fn handle_data_connection(
     Ok(())
 }
 
+/// Subscribes to the datagrams sent by the export script and handles them until it stops sending
+/// any, either because DCS is not running or because the mission ended
+fn handle_udp_link(
+    connection: &Connection,
+    handler: &mut FrameHandler,
+    quit_signal: &AtomicBool,
+) -> io::Result<()> {
+    let address = resolve(connection)?;
+    let local_address = if address.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local_address)?;
+    // Only accept datagrams from the export script
+    socket.connect(address)?;
+    socket.set_read_timeout(Some(SUBSCRIBE_INTERVAL))?;
+
+    let subscription = serde_json::to_string(&dcs::Subscription {
+        subscribe: connection.secret.clone(),
+    })?;
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+    let mut last_subscribed: Option<Instant> = None;
+    let mut last_received = Instant::now();
+    let mut connected = false;
+
+    while quit_signal.load(Relaxed) == false {
+        if last_subscribed.map_or(true, |time| time.elapsed() >= SUBSCRIBE_INTERVAL) {
+            socket.send(subscription.as_bytes())?;
+            last_subscribed = Some(Instant::now());
+        }
+        match socket.recv(&mut buf) {
+            Ok(len) => {
+                if !connected {
+                    println!("Connected to DCS");
+                    connected = true;
+                }
+                last_received = Instant::now();
+                match connection.encoding {
+                    Encoding::Json => handler.handle(&String::from_utf8_lossy(&buf[..len]))?,
+                    // Datagrams already have a length, so they are sent without the prefix
+                    Encoding::Binary => handler.handle_binary(&buf[..len])?,
+                }
+            }
+            // Windows reports that nothing is listening on the port when receiving, which only
+            // means the export script is not running yet
+            Err(err)
+                if err.kind() == ErrorKind::WouldBlock
+                    || err.kind() == ErrorKind::TimedOut
+                    || err.kind() == ErrorKind::ConnectionReset => {}
+            Err(err) => return Err(err),
+        }
+        if last_received.elapsed() >= UDP_TIMEOUT {
+            break;
+        }
+    }
+    if connected {
+        println!("DCS stopped sending data, waiting for mission restart");
+    }
+    Ok(())
+}
+
 pub fn run_data_worker(
-    data_handle: &RwLock<Option<FlightData>>,
+    config: &ConfigHandle,
+    data_handle: &RwLock<FlightDataSamples>,
     radar_handle: &RwLock<RadarMemory>,
+    link_handle: &RwLock<LinkStatus>,
+    mut recorder: Option<Recorder>,
+    relay: &RelayServer,
     quit_signal: &AtomicBool,
 ) {
     // Run thread while looking for possible panics
-    if let Err(err) = catch_unwind(|| {
+    if let Err(err) = catch_unwind(AssertUnwindSafe(|| {
         println!("Waiting for mission start");
+        let mut retry_delay = MIN_RETRY_DELAY;
         while quit_signal.load(Relaxed) == false {
-            match TcpStream::connect("127.0.0.1:28561") {
-                // Connected to DCS
-                Ok(stream) => {
-                    println!("Connected to DCS");
-                    if let Err(_) =
-                        handle_data_connection(stream, data_handle, radar_handle, quit_signal)
-                    {
-                        println!("Warning: DCS disconnected suddenly");
+            // Read the settings every time, in case they were changed
+            let connection = config.lock().unwrap().connection.clone();
+            let mut handler =
+                FrameHandler::new(&connection, data_handle, link_handle, &mut recorder, relay);
+            let result = match connection.transport {
+                Transport::Tcp => match connect(&connection) {
+                    // Connected to DCS
+                    Ok(stream) => {
+                        println!("Connected to DCS");
+                        retry_delay = MIN_RETRY_DELAY;
+                        handle_data_connection(stream, &mut handler, quit_signal)
                     }
+                    // The export script is not running yet
+                    Err(err) if err.kind() == ErrorKind::ConnectionRefused => Ok(()),
+                    // Anything else can happen when connecting to another computer, so keep
+                    // trying, but less often
+                    Err(err) => {
+                        println!("Warning: could not connect to DCS: {}", err);
+                        retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                        Ok(())
+                    }
+                },
+                Transport::Udp => {
+                    let result = handle_udp_link(&connection, &mut handler, quit_signal);
+                    retry_delay = if result.is_ok() {
+                        MIN_RETRY_DELAY
+                    } else {
+                        (retry_delay * 2).min(MAX_RETRY_DELAY)
+                    };
+                    result
                 }
-                // DCS closed connection
-                Err(err)
-                    if err.kind() == ErrorKind::ConnectionAborted
-                        || err.kind() == ErrorKind::ConnectionAborted =>
-                {
-                    println!(
-                        "Warning: DCS disconnected suddenly, did something happen? (Check dcs.log)"
-                    );
-                    sleep(Duration::from_millis(500))
-                }
-                // The export script is not running yet
-                Err(err) if err.kind() == ErrorKind::ConnectionRefused => (),
-                // Unexpected error
-                Err(err) => panic!("{}", err),
+            };
+            if let Err(err) = result {
+                println!(
+                    "Warning: DCS disconnected suddenly ({}), check dcs.log",
+                    err
+                );
             }
+            // Reset the state however the connection ended
+            radar_handle.write().unwrap().targets.clear();
+            data_handle.write().unwrap().clear();
+            *link_handle.write().unwrap() = LinkStatus::Disconnected;
             // Wait a bit before trying to connect again
-            sleep(Duration::from_millis(500));
+            sleep(retry_delay);
         }
-    }) {
+    })) {
         // Send the quit signal to the main thread
         quit_signal.store(true, Relaxed);
         // Finish unwinding the worker thread